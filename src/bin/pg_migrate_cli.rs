@@ -1,7 +1,17 @@
 use clap::{Parser, Subcommand};
-use pg_migrate::DbClient;
+use pg_migrate::config::Config;
+use pg_migrate::error::MigrateError;
+use pg_migrate::export::{self, Format};
+use pg_migrate::import::{self, Source};
+use pg_migrate::naming::NamingScheme;
+use pg_migrate::squash::squash;
+use pg_migrate::target::{print_report, resolve_targets, run_on_targets, Target};
+use pg_migrate::tenant::quote_ident;
+use pg_migrate::{check, DbClient, DEFAULT_MODULE};
 use dotenv::dotenv;
+use postgres::{Client, NoTls};
 use std::env;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "pg_migrate")]
@@ -9,12 +19,203 @@ use std::env;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Placeholder value as key=value, may be repeated
+    #[arg(long = "var", global = true, value_parser = parse_var)]
+    vars: Vec<(String, String)>,
+    /// Print the SQL that would run instead of applying it
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Write the schema snapshot after upgrading
+    #[arg(long, global = true)]
+    dump_schema: bool,
+    /// Refuse to upgrade unless the migrations replayed on a shadow database match
+    #[arg(long, global = true)]
+    shadow_check: bool,
+    /// Apply pending migrations older than the current revision instead of refusing to upgrade
+    #[arg(long, global = true)]
+    allow_out_of_order: bool,
+    /// Migration module from `[modules]` in pg_migrate.toml, defaults to the migrations in MIGRATION_DIR
+    #[arg(long, global = true)]
+    module: Option<String>,
+    /// Migrate the tenant in this schema
+    #[arg(long, global = true)]
+    tenant: Option<String>,
+    /// Run for every tenant schema from `[tenants]` in pg_migrate.toml
+    #[arg(long, global = true, conflicts_with = "tenant")]
+    all_tenants: bool,
+    /// Number of tenants, or targets with --parallel, migrated at the same time
+    #[arg(long, global = true)]
+    concurrency: Option<usize>,
+    /// Database URL, or a target or group from pg_migrate.toml, instead of DATABASE_URL; may be repeated
+    #[arg(long = "target", global = true)]
+    targets: Vec<String>,
+    /// Run on all targets at the same time instead of one after another, stopping at the first failure
+    #[arg(long, global = true)]
+    parallel: bool,
+}
+
+fn parse_var(var: &str) -> Result<(String, String), String> {
+    var.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("invalid placeholder `{}`, expected key=value", var))
+}
+
+/// Connects for a command that only reads `--tenant`, see `try_connect`.
+fn connect(cli: &Cli, database_url: &str) -> DbClient {
+    try_connect(cli, database_url, false).expect("Failed to initialize database")
+}
+
+/// Connects with the options of `cli`. A `--tenant` is created when `create_tenant` is set and
+/// must already exist otherwise; either way no bookkeeping tables are created in `public`.
+fn try_connect(cli: &Cli, database_url: &str, create_tenant: bool) -> Result<DbClient, MigrateError> {
+    let mut db_client = match &cli.tenant {
+        Some(tenant) => DbClient::new_for_tenant(database_url, tenant, create_tenant)?,
+        None => DbClient::new(database_url)?,
+    };
+    for (key, value) in &cli.vars {
+        db_client.set_var(key, value);
+    }
+    db_client.set_dry_run(cli.dry_run);
+    if cli.dump_schema {
+        db_client.set_dump_schema(true);
+    }
+    if cli.shadow_check {
+        db_client.set_shadow_check(true);
+    }
+    if cli.allow_out_of_order {
+        db_client.set_allow_out_of_order(true);
+    }
+    if let Some(module) = &cli.module {
+        db_client.set_module(module)?;
+    }
+    Ok(db_client)
+}
+
+/// Runs `f` on every `--target` and prints a report, exiting with an error if any target failed.
+fn fan_out<F>(cli: &Cli, create_tenant: bool, f: F)
+where
+    F: Fn(&Target, &mut DbClient) -> Result<(), MigrateError> + Sync,
+{
+    let config = Config::load().expect("Failed to load config");
+    let targets = resolve_targets(&config, &cli.targets).expect("Failed to resolve targets");
+    let concurrency = cli.parallel.then(|| cli.concurrency.unwrap_or(targets.len()));
+    let reports = run_on_targets(&targets, concurrency, |target| {
+        let mut db_client = try_connect(cli, &target.url, create_tenant)?;
+        f(target, &mut db_client)?;
+        db_client.current_revision()
+    });
+    if !print_report(&reports) {
+        std::process::exit(1);
+    }
+}
+
+/// The migration directory of `--module`, found without connecting to the database.
+fn migration_dir(cli: &Cli) -> PathBuf {
+    match cli.module.as_deref() {
+        None | Some(DEFAULT_MODULE) => PathBuf::from(DbClient::get_migration_dir()),
+        Some(module) => {
+            let config = Config::load().expect("Failed to load config");
+            let dir = config.modules.get(module).unwrap_or_else(|| panic!("{}", MigrateError::UnknownModule(module.to_string())));
+            PathBuf::from(dir)
+        }
+    }
+}
+
+/// Prints the problems `check` finds and exits with an error if there are any.
+fn check_migrations(cli: &Cli) {
+    let migration_dir = migration_dir(cli);
+    let problems = check::check(&migration_dir).expect("Failed to read migrations");
+    if problems.is_empty() {
+        println!("No problems found in {}", migration_dir.display());
+        return;
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    println!("{} problem(s) found in {}", problems.len(), migration_dir.display());
+    std::process::exit(1);
+}
+
+/// Re-parents `revision` onto `onto` and exits with an error if the result has problems.
+fn rebase(cli: &Cli, revision: &str, onto: &str) {
+    let migration_dir = migration_dir(cli);
+    let problems = check::rebase(&migration_dir, revision, onto).expect("Failed to rebase migration");
+    println!("Rebased {} onto {}", revision, onto);
+    if !problems.is_empty() {
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        println!("{} problem(s) remain in {}", problems.len(), migration_dir.display());
+        std::process::exit(1);
+    }
+}
+
+/// Squashes a range of migrations without connecting to the database, so references to the
+/// current revision are refused.
+fn squash_migrations(cli: &Cli, from: &str, to: &str, name: Option<&str>) {
+    let current = || Err(MigrateError::UnknownRevision("current".to_string()));
+    squash(&migration_dir(cli), &NamingScheme::from_env(), from, to, name, current).expect("Failed to squash migrations");
+}
+
+/// Exports the migrations without connecting to the database.
+fn export_migrations(cli: &Cli, format: Format, output: &str) {
+    let mut vars = Config::load().expect("Failed to load config").placeholder_vars();
+    vars.extend(cli.vars.iter().cloned());
+    export::export(&migration_dir(cli), format, Path::new(output), &vars).expect("Failed to export migrations");
+}
+
+/// Converts the migrations, then connects only if the other tool recorded applied migrations
+/// there, so an unused database does not get bookkeeping tables.
+fn import_migrations(cli: &Cli, source: Source, dir: &str) {
+    let Some(import) = import::convert(source, Path::new(dir), &migration_dir(cli)).expect("Failed to import migrations") else {
+        return;
+    };
+    let Ok(database_url) = env::var("DATABASE_URL") else {
+        println!("DATABASE_URL is not set, no migrations were marked as applied");
+        return;
+    };
+    let mut client = Client::connect(&database_url, NoTls).expect("Failed to connect to database");
+    if let Some(tenant) = &cli.tenant {
+        client.batch_execute(&format!("SET search_path TO {}, public", quote_ident(tenant))).expect("Failed to set tenant");
+    }
+    let applied = import::applied_revisions(source, &mut client, &import.migrations).expect("Failed to read applied migrations");
+    if applied.is_empty() {
+        return;
+    }
+    let mut db_client = try_connect(cli, &database_url, true).expect("Failed to initialize database");
+    db_client.mark_imported(source, &import.migrations).expect("Failed to mark imported migrations");
+}
+
+fn migrate(cli: &Cli, database_url: &str, upgrade: bool, head: bool, target: Option<&str>, count: Option<&i32>) {
+    if !cli.targets.is_empty() {
+        return fan_out(cli, upgrade, |_, db_client| {
+            if cli.all_tenants {
+                db_client.run_tenant_migrations(upgrade, head, target, count, cli.concurrency)
+            } else {
+                db_client.run_migrations(upgrade, head, target, count)
+            }
+        });
+    }
+    let mut db_client = try_connect(cli, database_url, upgrade).expect("Failed to initialize database");
+    if cli.all_tenants {
+        if db_client.run_tenant_migrations(upgrade, head, target, count, cli.concurrency).is_err() {
+            std::process::exit(1);
+        }
+    } else {
+        db_client.run_migrations(upgrade, head, target, count).expect("Failed to run migrations");
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     New {
         name: String,
+        /// Generate the SQL by diffing the database at head against the desired schema
+        #[arg(long)]
+        autogenerate: bool,
+        /// Desired schema for --autogenerate, defaults to `schema_file` from pg_migrate.toml
+        #[arg(long, requires = "autogenerate")]
+        schema: Option<String>,
     },
     Upgrade {
         #[command(subcommand)]
@@ -27,69 +228,198 @@ enum Commands {
     Head {},
     Current {},
     History {},
+    /// Show every migration as applied, pending or pending (out of order), for all modules unless --module is given
+    Status {},
+    /// Run the seed files that have not run yet
+    Seed {
+        /// Also run the seeds in <SEED_DIR>/<env>
+        #[arg(long)]
+        env: Option<String>,
+    },
+    /// Downgrade all migrations, forget applied seeds and upgrade to head
+    Reset {},
+    Schema {
+        #[command(subcommand)]
+        command: SchemaSubcommands,
+    },
+    /// Check on a scratch database that every down migration undoes its up migration
+    CheckRoundtrip {},
+    /// Replay the migrations on a shadow database and compare it with the database
+    ValidateShadow {},
+    /// Validate the migration directory without connecting to the database, exiting non-zero on problems
+    Check {},
+    /// Point the Down Revision of <REVISION> at another revision and check the result
+    Rebase {
+        revision: String,
+        /// The new parent, usually the other head
+        #[arg(long)]
+        onto: String,
+    },
+    /// Show a migration's parents, children, files, status and SQL
+    Show {
+        /// Revision, unique revision prefix or migration name
+        revision: String,
+    },
+    /// Browse the revision graph, preview SQL and upgrade or downgrade interactively
+    #[cfg(feature = "tui")]
+    Tui {},
+    /// Replace the migrations from <FROM> to <TO> with a single migration
+    Squash {
+        from: String,
+        to: String,
+        /// Name of the squashed migration
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Convert another tool's migrations into MIGRATION_DIR and mark the ones it applied as applied
+    Import {
+        /// sqlx, diesel, refinery, flyway or alembic
+        #[arg(long)]
+        from: Source,
+        /// The other tool's migration directory, e.g. `alembic/versions`
+        dir: String,
+    },
+    /// Write the migrations in order for another tool, or as a single script
+    Export {
+        /// flyway, sqlx or plain-sql
+        #[arg(long)]
+        to: Format,
+        /// Directory to write to, or the script file for plain-sql
+        output: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SchemaSubcommands {
+    /// Write the normalized schema of the database
+    Dump {
+        /// Defaults to `schema_file` from pg_migrate.toml, or schema.sql
+        #[arg(long)]
+        output: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
 enum UpgradeSubcommands {
     Head,
-    MigrationId { id: String },
+    MigrationId {
+        /// Revision, unique prefix, name, or head, head~N, base, current+N or current-N
+        id: String,
+    },
     Number { num: i32 },
 }
 
 #[derive(Subcommand)]
 enum DowngradeSubcommands {
-    MigrationId { id: String },
+    MigrationId {
+        /// Revision, unique prefix, name, or head~N, base or current-N
+        id: String,
+    },
     Number { num: i32 },
 }
 
 fn main() {
     dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-
     let cli = Cli::parse();
 
     match &cli.command {
-        Commands::New { name } => {
-            let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
-            db_client.create_new_migration(name).expect("Failed to create new migration");
+        Commands::Check {} => return check_migrations(&cli),
+        Commands::Rebase { revision, onto } => return rebase(&cli, revision, onto),
+        Commands::Squash { from, to, name } => return squash_migrations(&cli, from, to, name.as_deref()),
+        Commands::Export { to, output } => return export_migrations(&cli, *to, output),
+        Commands::Import { from, dir } => return import_migrations(&cli, *from, dir),
+        _ => {}
+    }
+
+    let database_url = match env::var("DATABASE_URL") {
+        Ok(database_url) => database_url,
+        Err(_) if !cli.targets.is_empty() => String::new(),
+        Err(_) => panic!("DATABASE_URL must be set"),
+    };
+
+    match &cli.command {
+        Commands::New { name, autogenerate, schema } => {
+            let mut db_client = connect(&cli, &database_url);
+            if *autogenerate {
+                db_client.autogenerate_migration(name, schema.as_deref()).expect("Failed to autogenerate migration");
+            } else {
+                db_client.create_new_migration(name).expect("Failed to create new migration");
+            }
         }
         Commands::Head {} => {
-            let db_client = DbClient::new(&database_url).expect("Failed to initialize database");
+            let db_client = connect(&cli, &database_url);
             db_client.get_head().expect("Failed to get head");
         }
         Commands::Current {} => {
-            let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
+            let mut db_client = connect(&cli, &database_url);
             db_client.get_current();
         }
         Commands::History {} => {
-            let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
+            let mut db_client = connect(&cli, &database_url);
             db_client.get_history().expect("Failed to get history");
         }
-
-        Commands::Upgrade { command } => match command {
-            UpgradeSubcommands::Head => {
-                let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
-                db_client.run_migrations(true, true, None, None).expect("Failed to run migrations");
+        Commands::Status {} if !cli.targets.is_empty() => fan_out(&cli, false, |target, db_client| {
+            println!("Target: {}", target.name);
+            db_client.get_status()
+        }),
+        Commands::Status {} => {
+            let mut db_client = connect(&cli, &database_url);
+            if cli.all_tenants {
+                db_client.get_status_all_tenants().expect("Failed to get status");
+            } else if cli.module.is_some() || cli.tenant.is_some() {
+                db_client.get_status().expect("Failed to get status");
+            } else {
+                db_client.get_status_all().expect("Failed to get status");
             }
-            UpgradeSubcommands::MigrationId { id } => {
-                let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
-                db_client.run_migrations(true, false, Some(id), None).expect("Failed to run migrations");
+        }
+        Commands::Seed { env } => {
+            let mut db_client = connect(&cli, &database_url);
+            db_client.run_seeds(env.as_deref()).expect("Failed to run seeds");
+        }
+        Commands::Reset {} => {
+            let mut db_client = try_connect(&cli, &database_url, true).expect("Failed to initialize database");
+            db_client.reset().expect("Failed to reset database");
+        }
+        Commands::Schema { command } => match command {
+            SchemaSubcommands::Dump { output } => {
+                let mut db_client = connect(&cli, &database_url);
+                db_client.dump_schema(output.as_deref()).expect("Failed to dump schema");
             }
-            UpgradeSubcommands::Number { num } => {
-                let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
-                db_client.run_migrations(true, false, None, Some(num)).expect("Failed to run migrations");
+        }
+        Commands::CheckRoundtrip {} => {
+            let mut db_client = connect(&cli, &database_url);
+            if !db_client.check_roundtrip().expect("Failed to check migrations") {
+                std::process::exit(1);
             }
         }
+        Commands::ValidateShadow {} => {
+            let mut db_client = connect(&cli, &database_url);
+            if !db_client.validate_shadow().expect("Failed to validate shadow database") {
+                std::process::exit(1);
+            }
+        }
+        Commands::Check {} | Commands::Rebase { .. } | Commands::Squash { .. } | Commands::Export { .. } | Commands::Import { .. } => {
+            unreachable!()
+        }
+        Commands::Show { revision } => {
+            let mut db_client = connect(&cli, &database_url);
+            db_client.show_migration(revision).expect("Failed to show migration");
+        }
+        #[cfg(feature = "tui")]
+        Commands::Tui {} => {
+            let mut db_client = connect(&cli, &database_url);
+            pg_migrate::tui::run(&mut db_client).expect("Failed to run the TUI");
+        }
+
+        Commands::Upgrade { command } => match command {
+            UpgradeSubcommands::Head => migrate(&cli, &database_url, true, true, None, None),
+            UpgradeSubcommands::MigrationId { id } => migrate(&cli, &database_url, true, false, Some(id), None),
+            UpgradeSubcommands::Number { num } => migrate(&cli, &database_url, true, false, None, Some(num)),
+        }
 
         Commands::Downgrade { command } => match command{
-            DowngradeSubcommands::MigrationId { id } => {
-                let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
-                db_client.run_migrations(false, false, Some(id), None).expect("Failed to run migrations");
-            }
-            DowngradeSubcommands::Number { num } => {
-                let mut db_client = DbClient::new(&database_url).expect("Failed to initialize database");
-                db_client.run_migrations(false, false, None, Some(num)).expect("Failed to run migrations");
-            }
+            DowngradeSubcommands::MigrationId { id } => migrate(&cli, &database_url, false, false, Some(id), None),
+            DowngradeSubcommands::Number { num } => migrate(&cli, &database_url, false, false, None, Some(num)),
         }
     }
 }
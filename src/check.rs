@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::error::MigrateError;
+use crate::migration::{load_migrations, read_headers, Migration};
+use crate::revision;
+
+/// Validates the migration directory without a database and returns one message per problem.
+///
+/// Checks that file names are valid, every up file has a down file and the other way round, the
+/// up and down headers agree, revisions are unique, every `Down Revision` and unqualified
+/// `Depends On` revision exists and the revisions form a single chain ending in one head.
+pub fn check(dir: &Path) -> io::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    check_files(dir, &mut problems)?;
+
+    let migrations = load_migrations(dir)?;
+    let mut seen: BTreeMap<&str, &Migration> = BTreeMap::new();
+    for migration in &migrations {
+        if let Some(other) = seen.insert(&migration.revision, migration) {
+            problems.push(format!(
+                "Revision {} is used by both {} and {}",
+                migration.revision,
+                display(other),
+                display(migration)
+            ));
+        }
+        check_headers(migration, &mut problems)?;
+    }
+
+    for migration in &migrations {
+        for parent in &migration.down_revisions {
+            match migrations.iter().find(|m| &m.revision == parent) {
+                None => problems.push(format!(
+                    "{} has Down Revision {}, which does not exist; point it at an existing revision",
+                    display(migration),
+                    parent
+                )),
+                Some(parent_migration) if parent_migration.order > migration.order => problems.push(format!(
+                    "{} is part of a cycle through Down Revision {}",
+                    display(migration),
+                    parent
+                )),
+                Some(_) => {}
+            }
+        }
+        // `<module>:<revision>` dependencies point into another module's directory.
+        for dependency in migration.depends_on.iter().filter(|dependency| !dependency.contains(':')) {
+            if !migrations.iter().any(|m| &m.revision == dependency) {
+                problems.push(format!(
+                    "{} has Depends On {}, which does not exist; point it at an existing revision",
+                    display(migration),
+                    dependency
+                ));
+            }
+        }
+    }
+
+    let heads = heads(&migrations);
+    if heads.len() > 1 {
+        let revisions: Vec<&str> = heads.iter().map(|m| m.revision.as_str()).collect();
+        problems.push(format!(
+            "Found {} heads: {}; the migrations were created on diverging branches, point the newer one's \
+            Down Revision at the other head",
+            heads.len(),
+            revisions.join(", ")
+        ));
+    }
+    Ok(problems)
+}
+
+/// Points the `Down Revision` of `revision` at `onto`, in its up and down files, and returns the
+/// problems `check` finds in the result.
+///
+/// Both accept the references of `revision::resolve` that need no database, so `--onto base`
+/// turns the migration into a base migration.
+pub fn rebase(dir: &Path, revision: &str, onto: &str) -> Result<Vec<String>, MigrateError> {
+    let migrations = load_migrations(dir)?;
+    let resolve = |reference: &str| {
+        revision::resolve(&migrations, reference, || Err(MigrateError::UnknownRevision(reference.to_string())))
+    };
+    let moved = resolve(revision)?.ok_or_else(|| MigrateError::UnknownRevision(revision.to_string()))?;
+    let migration = migrations.iter().find(|m| m.revision == moved).unwrap();
+    let parents: Vec<String> = resolve(onto)?.into_iter().collect();
+    if parents.iter().any(|parent| descendants(&migrations, &moved).contains(parent)) {
+        return Err(MigrateError::CyclicRebase { revision: revision.to_string(), onto: onto.to_string() });
+    }
+
+    migration.set_down_revisions(&parents)?;
+    Ok(check(dir)?)
+}
+
+/// `revision` and every migration that descends from it.
+fn descendants(migrations: &[Migration], revision: &str) -> Vec<String> {
+    let mut descendants = vec![revision.to_string()];
+    let mut index = 0;
+    while index < descendants.len() {
+        for migration in migrations {
+            if migration.down_revisions.contains(&descendants[index]) && !descendants.contains(&migration.revision) {
+                descendants.push(migration.revision.clone());
+            }
+        }
+        index += 1;
+    }
+    descendants
+}
+
+/// Migrations that no other migration names as a down revision.
+pub fn heads(migrations: &[Migration]) -> Vec<&Migration> {
+    migrations.iter()
+        .filter(|migration| !migrations.iter().any(|m| m.down_revisions.contains(&migration.revision)))
+        .collect()
+}
+
+fn check_files(dir: &Path, problems: &mut Vec<String>) -> io::Result<()> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+
+    for path in &entries {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_dir() {
+            // Repeatable migrations and SQL hooks have their own directories.
+            if file_name == "repeatable" || file_name == "hooks" {
+                continue;
+            }
+            if !path.join("up.sql").is_file() {
+                problems.push(format!("Directory {} has no up.sql", file_name));
+            } else if !path.join("down.sql").is_file() {
+                problems.push(format!("Directory {} has no down.sql", file_name));
+            }
+        } else if let Some(stem) = file_name.strip_suffix("_up.sql") {
+            if !path.with_file_name(format!("{}_down.sql", stem)).is_file() {
+                problems.push(format!("{} has no matching {}_down.sql", file_name, stem));
+            }
+        } else if let Some(stem) = file_name.strip_suffix("_down.sql") {
+            if !path.with_file_name(format!("{}_up.sql", stem)).is_file() {
+                problems.push(format!("{} has no matching {}_up.sql", file_name, stem));
+            }
+        } else if file_name.ends_with(".sql") {
+            problems.push(format!("{} is not a migration file name, expected <stem>_up.sql or <stem>_down.sql", file_name));
+            continue;
+        } else {
+            continue;
+        }
+        if file_name.contains(char::is_whitespace) {
+            problems.push(format!("{} contains whitespace; rename it without spaces", file_name));
+        }
+    }
+    Ok(())
+}
+
+/// The `Revision` and `Down Revision` headers of the down file must match the up file's.
+fn check_headers(migration: &Migration, problems: &mut Vec<String>) -> io::Result<()> {
+    let (Some(up_file), Some(down_file)) = (migration.up_files.first(), migration.down_files.first()) else {
+        return Ok(());
+    };
+    let up_headers = read_headers(up_file)?;
+    let down_headers = read_headers(down_file)?;
+    for key in ["Revision", "Down Revision"] {
+        let up = up_headers.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        let down = down_headers.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        if let (Some(up), Some(down)) = (up, down) {
+            if up != down {
+                problems.push(format!(
+                    "{} has {} {} in the up file but {} in the down file",
+                    display(migration),
+                    key,
+                    up,
+                    down
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn display(migration: &Migration) -> String {
+    migration.path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::{write_migration, TempDir};
+
+    #[test]
+    fn test_valid_migrations() {
+        assert!(check(Path::new("migrations")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_problems() {
+        let dir = TempDir::new();
+        write_migration(&dir, "a", None, "users", "", "");
+        write_migration(&dir, "b", Some("a"), "wallets", "-- Depends On: a, ghost, auth:x\n", "");
+        write_migration(&dir, "c", Some("a"), "orders", "", "");
+        write_migration(&dir, "d", Some("missing"), "items", "", "");
+        fs::write(dir.join("e_tags_up.sql"), "-- Revision: e\n-- Down Revision: d\n").unwrap();
+        fs::write(dir.join("f_down.sql"), "-- Revision: f\n").unwrap();
+        fs::write(dir.join("notes.sql"), "SELECT 1;\n").unwrap();
+        fs::write(dir.join("c_orders_down.sql"), "-- Revision: c\n-- Down Revision: b\n").unwrap();
+        fs::create_dir_all(dir.join("hooks")).unwrap();
+        fs::write(dir.join("hooks").join("before_all.sql"), "SELECT 1;\n").unwrap();
+        fs::create_dir_all(dir.join("repeatable")).unwrap();
+        fs::write(dir.join("repeatable").join("views.sql"), "SELECT 1;\n").unwrap();
+
+        let problems = check(&dir).unwrap();
+
+        assert_eq!(problems, vec![
+            "e_tags_up.sql has no matching e_tags_down.sql",
+            "f_down.sql has no matching f_up.sql",
+            "notes.sql is not a migration file name, expected <stem>_up.sql or <stem>_down.sql",
+            "c_orders_up.sql has Down Revision a in the up file but b in the down file",
+            "b_wallets_up.sql has Depends On ghost, which does not exist; point it at an existing revision",
+            "d_items_up.sql has Down Revision missing, which does not exist; point it at an existing revision",
+            "Found 3 heads: b, c, e; the migrations were created on diverging branches, point the newer one's \
+            Down Revision at the other head",
+        ]);
+    }
+
+    #[test]
+    fn test_rebase() {
+        let dir = TempDir::new();
+        write_migration(&dir, "a", None, "users", "", "");
+        write_migration(&dir, "b", Some("a"), "wallets", "", "");
+        write_migration(&dir, "c", Some("a"), "orders", "", "");
+        fs::write(dir.join("d_items_up.sql"), "-- Revision: d\nCREATE TABLE items (id INT);\n").unwrap();
+        fs::write(dir.join("d_items_down.sql"), "-- Revision: d\nDROP TABLE items;\n").unwrap();
+
+        let before = check(&dir).unwrap().len();
+        let rebased = rebase(&dir, "c", "b").unwrap();
+        let down_sql = fs::read_to_string(dir.join("c_orders_down.sql")).unwrap();
+        let cyclic = rebase(&dir, "b", "c");
+        let unknown = rebase(&dir, "c", "z");
+        let rebased_base = rebase(&dir, "d", "c").unwrap();
+        let up_sql = fs::read_to_string(dir.join("d_items_up.sql")).unwrap();
+
+        assert_eq!(before, 1);
+        assert_eq!(rebased, vec!["Found 2 heads: c, d; the migrations were created on diverging branches, point the newer \
+            one's Down Revision at the other head"]);
+        assert_eq!(down_sql, "-- Revision: c\n-- Down Revision: b\n");
+        assert!(matches!(cyclic, Err(MigrateError::CyclicRebase { .. })));
+        assert!(matches!(unknown, Err(MigrateError::UnknownRevision(revision)) if revision == "z"));
+        assert!(rebased_base.is_empty());
+        assert_eq!(up_sql, "-- Revision: d\n-- Down Revision: c\nCREATE TABLE items (id INT);\n");
+    }
+}
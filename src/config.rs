@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use dotenv::dotenv;
+use serde::Deserialize;
+use crate::tenant::TenantsConfig;
+
+/// Settings read from `pg_migrate.toml` (or the file named by `PG_MIGRATE_CONFIG`).
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    /// Write the schema snapshot after every upgrade.
+    #[serde(default)]
+    pub dump_schema: bool,
+    pub schema_file: Option<String>,
+    /// Validate the migrations against a shadow database before every upgrade.
+    #[serde(default)]
+    pub shadow_check: bool,
+    /// Database the shadow check may wipe and reuse, instead of a temporary one.
+    pub shadow_database_url: Option<String>,
+    /// Apply pending migrations that sort before the current revision instead of refusing to upgrade.
+    #[serde(default)]
+    pub allow_out_of_order: bool,
+    /// Named migration locations, each with its own revision chain, e.g. `billing = "services/billing/migrations"`.
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tenants: TenantsConfig,
+    /// Named databases for `--target`.
+    #[serde(default)]
+    pub targets: BTreeMap<String, String>,
+    /// Named groups of targets or database URLs for `--target`.
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Config {
+    pub fn load() -> io::Result<Self> {
+        dotenv().ok();
+        let path = env::var("PG_MIGRATE_CONFIG").unwrap_or_else(|_| "pg_migrate.toml".to_string());
+        if !Path::new(&path).is_file() {
+            return Ok(Config::default());
+        }
+        Config::parse(&fs::read_to_string(&path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// `SHADOW_DATABASE_URL` from the environment, or `shadow_database_url`.
+    pub fn shadow_database_url(&self) -> Option<String> {
+        env::var("SHADOW_DATABASE_URL").ok().or_else(|| self.shadow_database_url.clone())
+    }
+
+    /// Placeholder values from `[vars]`, overridden by `PG_MIGRATE_VAR_<NAME>` environment variables.
+    pub fn placeholder_vars(&self) -> BTreeMap<String, String> {
+        let mut vars = self.vars.clone();
+        for (key, value) in env::vars() {
+            if let Some(name) = key.strip_prefix("PG_MIGRATE_VAR_") {
+                vars.insert(name.to_lowercase(), value);
+            }
+        }
+        vars
+    }
+}
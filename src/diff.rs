@@ -0,0 +1,363 @@
+use std::collections::BTreeSet;
+use crate::schema::{Column, Schema, Table};
+
+/// A DDL statement, with a note when it needs a human to check it before it runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub sql: String,
+    pub review: Option<String>,
+}
+
+impl Statement {
+    fn new(sql: String) -> Self {
+        Statement { sql, review: None }
+    }
+
+    fn review(sql: String, review: String) -> Self {
+        Statement { sql, review: Some(review) }
+    }
+}
+
+/// Statements migrating between two schemas in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaDiff {
+    pub up: Vec<Statement>,
+    pub down: Vec<Statement>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.up.is_empty()
+    }
+
+    /// Number of statements marked for review in either direction.
+    pub fn review_count(&self) -> usize {
+        self.up.iter().chain(self.down.iter()).filter(|statement| statement.review.is_some()).count()
+    }
+}
+
+/// Compares two schemas, producing the statements that turn `from` into `to` and back.
+pub fn diff(from: &Schema, to: &Schema) -> SchemaDiff {
+    SchemaDiff {
+        up: plan(from, to),
+        down: plan(to, from),
+    }
+}
+
+/// Renders statements as SQL, each reviewed statement preceded by a `-- REVIEW:` comment.
+pub fn render(statements: &[Statement]) -> String {
+    let mut sql = String::new();
+    for statement in statements {
+        if let Some(review) = &statement.review {
+            sql.push_str(&format!("-- REVIEW: {}\n", review));
+        }
+        sql.push_str(&statement.sql);
+        sql.push('\n');
+    }
+    sql
+}
+
+/// Lists the objects that differ between two schemas, e.g. `table public.users: column email added`.
+pub fn describe(from: &Schema, to: &Schema) -> Vec<String> {
+    let mut differences = Vec::new();
+    describe_keys("schema", &from.schemas.iter().collect(), &to.schemas.iter().collect(), &mut differences);
+    describe_map("sequence", &from.sequences, &to.sequences, &mut differences);
+    describe_keys("table", &from.tables.keys().collect(), &to.tables.keys().collect(), &mut differences);
+    for (name, table) in &from.tables {
+        let Some(target) = to.tables.get(name) else {
+            continue;
+        };
+        for column in &table.columns {
+            match target.column(&column.name) {
+                None => differences.push(format!("table {}: column {} removed", name, column.name)),
+                Some(other) if other != column => differences.push(format!(
+                    "table {}: column {} changed from `{}` to `{}`", name, column.name, column.to_sql(), other.to_sql()
+                )),
+                _ => {}
+            }
+        }
+        for column in &target.columns {
+            if table.column(&column.name).is_none() {
+                differences.push(format!("table {}: column {} added", name, column.name));
+            }
+        }
+        describe_map(&format!("table {}: constraint", name), &table.constraints, &target.constraints, &mut differences);
+    }
+    describe_map("index", &from.indexes, &to.indexes, &mut differences);
+    describe_map("function", &from.functions, &to.functions, &mut differences);
+    describe_map("view", &from.views, &to.views, &mut differences);
+    differences
+}
+
+fn describe_keys(kind: &str, from: &BTreeSet<&String>, to: &BTreeSet<&String>, differences: &mut Vec<String>) {
+    for name in from.difference(to) {
+        differences.push(format!("{} {} removed", kind, name));
+    }
+    for name in to.difference(from) {
+        differences.push(format!("{} {} added", kind, name));
+    }
+}
+
+fn describe_map<T: PartialEq>(
+    kind: &str,
+    from: &std::collections::BTreeMap<String, T>,
+    to: &std::collections::BTreeMap<String, T>,
+    differences: &mut Vec<String>,
+) {
+    describe_keys(kind, &from.keys().collect(), &to.keys().collect(), differences);
+    for (name, value) in from {
+        if to.get(name).is_some_and(|other| other != value) {
+            differences.push(format!("{} {} changed", kind, name));
+        }
+    }
+}
+
+fn plan(from: &Schema, to: &Schema) -> Vec<Statement> {
+    let mut statements = Vec::new();
+
+    for name in to.schemas.difference(&from.schemas) {
+        statements.push(Statement::new(format!("CREATE SCHEMA {};", name)));
+    }
+
+    for (name, view) in &from.views {
+        if to.views.get(name) != Some(view) {
+            let kind = if view.materialized { "MATERIALIZED VIEW" } else { "VIEW" };
+            statements.push(Statement::new(format!("DROP {} {};", kind, name)));
+        }
+    }
+
+    for signature in from.functions.keys() {
+        if !to.functions.contains_key(signature) {
+            statements.push(Statement::new(format!("DROP FUNCTION {};", signature)));
+        }
+    }
+
+    for (name, index) in &from.indexes {
+        if to.indexes.get(name) != Some(index) {
+            statements.push(Statement::new(format!("DROP INDEX {};", name)));
+        }
+    }
+
+    for (name, table) in &from.tables {
+        let Some(target) = to.tables.get(name) else {
+            continue;
+        };
+        for (constraint_name, constraint) in &table.constraints {
+            if target.constraints.get(constraint_name) != Some(constraint) {
+                statements.push(Statement::new(format!("ALTER TABLE {} DROP CONSTRAINT {};", name, constraint_name)));
+            }
+        }
+    }
+
+    let dropped: Vec<&Table> = from.tables.values().filter(|table| !to.tables.contains_key(&table.name)).collect();
+    let added: Vec<&Table> = to.tables.values().filter(|table| !from.tables.contains_key(&table.name)).collect();
+    for table in &dropped {
+        let sql = format!("DROP TABLE {};", table.name);
+        match added.iter().find(|other| same_columns(table, other)) {
+            Some(other) => statements.push(Statement::review(
+                sql,
+                format!("{} may have been renamed to {}; consider ALTER TABLE {} RENAME TO ...", table.name, other.name, table.name),
+            )),
+            None => statements.push(Statement::new(sql)),
+        }
+    }
+
+    for (name, sequence) in &to.sequences {
+        match from.sequences.get(name) {
+            None => statements.push(Statement::new(sequence.to_sql())),
+            Some(existing) if existing != sequence => statements.push(Statement::new(format!(
+                "ALTER SEQUENCE {} AS {} INCREMENT BY {} MINVALUE {} MAXVALUE {} {};",
+                name, sequence.data_type, sequence.increment, sequence.min_value, sequence.max_value,
+                if sequence.cycle { "CYCLE" } else { "NO CYCLE" }
+            ))),
+            _ => {}
+        }
+    }
+
+    for table in &added {
+        match dropped.iter().find(|other| same_columns(table, other)) {
+            Some(other) => statements.push(Statement::review(
+                table.to_sql(),
+                format!("{} may be a rename of {}", table.name, other.name),
+            )),
+            None => statements.push(Statement::new(table.to_sql())),
+        }
+    }
+
+    for (name, table) in &from.tables {
+        if let Some(target) = to.tables.get(name) {
+            plan_columns(table, target, &mut statements);
+        }
+    }
+
+    for (name, table) in &to.tables {
+        match from.tables.get(name) {
+            None => statements.extend(table.foreign_keys_sql().into_iter().map(Statement::new)),
+            Some(existing) => {
+                for (constraint_name, constraint) in &table.constraints {
+                    if existing.constraints.get(constraint_name) != Some(constraint) {
+                        statements.push(Statement::new(format!(
+                            "ALTER TABLE {} ADD CONSTRAINT {} {};", name, constraint_name, constraint.definition
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    for (name, index) in &to.indexes {
+        if from.indexes.get(name) != Some(index) {
+            statements.push(Statement::new(format!("{};", index.definition)));
+        }
+    }
+
+    for name in from.sequences.keys() {
+        if !to.sequences.contains_key(name) {
+            statements.push(Statement::new(format!("DROP SEQUENCE IF EXISTS {};", name)));
+        }
+    }
+
+    for (signature, function) in &to.functions {
+        if from.functions.get(signature) != Some(function) {
+            statements.push(Statement::new(format!("{};", function.definition.trim_end())));
+        }
+    }
+
+    for (name, view) in &to.views {
+        if from.views.get(name) != Some(view) {
+            statements.push(Statement::new(format!("{};", view.to_sql())));
+        }
+    }
+
+    for name in from.schemas.difference(&to.schemas) {
+        statements.push(Statement::new(format!("DROP SCHEMA {};", name)));
+    }
+
+    statements
+}
+
+fn plan_columns(table: &Table, target: &Table, statements: &mut Vec<Statement>) {
+    let name = &table.name;
+    let added: Vec<&Column> = target.columns.iter().filter(|column| table.column(&column.name).is_none()).collect();
+
+    for column in &table.columns {
+        let Some(other) = target.column(&column.name) else {
+            let sql = format!("ALTER TABLE {} DROP COLUMN {};", name, column.name);
+            match added.iter().find(|added| added.data_type == column.data_type) {
+                Some(added) => statements.push(Statement::review(
+                    sql,
+                    format!("{}.{} may have been renamed to {}; consider ALTER TABLE {} RENAME COLUMN {} TO {}",
+                        name, column.name, added.name, name, column.name, added.name),
+                )),
+                None => statements.push(Statement::new(sql)),
+            }
+            continue;
+        };
+
+        if other.data_type != column.data_type {
+            statements.push(Statement::review(
+                format!("ALTER TABLE {} ALTER COLUMN {} TYPE {} USING {}::{};", name, column.name, other.data_type, column.name, other.data_type),
+                format!("type of {}.{} changes from {} to {}; check the USING conversion", name, column.name, column.data_type, other.data_type),
+            ));
+        }
+        if other.default != column.default {
+            match &other.default {
+                Some(default) => statements.push(Statement::new(format!("ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {};", name, column.name, default))),
+                None => statements.push(Statement::new(format!("ALTER TABLE {} ALTER COLUMN {} DROP DEFAULT;", name, column.name))),
+            }
+        }
+        if other.not_null != column.not_null {
+            let action = if other.not_null { "SET" } else { "DROP" };
+            statements.push(Statement::new(format!("ALTER TABLE {} ALTER COLUMN {} {} NOT NULL;", name, column.name, action)));
+        }
+    }
+
+    for column in added {
+        let sql = format!("ALTER TABLE {} ADD COLUMN {};", name, column.to_sql());
+        if column.not_null && column.default.is_none() {
+            statements.push(Statement::review(sql, format!("{}.{} is NOT NULL without a default and fails on a non-empty table", name, column.name)));
+        } else {
+            statements.push(Statement::new(sql));
+        }
+    }
+}
+
+fn same_columns(a: &Table, b: &Table) -> bool {
+    a.columns.len() == b.columns.len()
+        && a.columns.iter().zip(b.columns.iter()).all(|(a, b)| a.name == b.name && a.data_type == b.data_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::schema::Constraint;
+
+    fn column(name: &str, data_type: &str, not_null: bool) -> Column {
+        Column { name: name.to_string(), data_type: data_type.to_string(), not_null, default: None }
+    }
+
+    fn table(name: &str, columns: Vec<Column>) -> Table {
+        Table { name: name.to_string(), columns, constraints: BTreeMap::new() }
+    }
+
+    fn schema(tables: Vec<Table>) -> Schema {
+        let mut schema = Schema::default();
+        for table in tables {
+            schema.tables.insert(table.name.clone(), table);
+        }
+        schema
+    }
+
+    #[test]
+    fn test_added_table_and_column() {
+        let from = schema(vec![table("public.users", vec![column("id", "integer", true)])]);
+        let mut orders = table("public.orders", vec![column("id", "integer", true), column("user_id", "integer", true)]);
+        orders.constraints.insert("orders_user_id_fkey".to_string(), Constraint {
+            name: "orders_user_id_fkey".to_string(),
+            definition: "FOREIGN KEY (user_id) REFERENCES users(id)".to_string(),
+        });
+        let to = schema(vec![
+            table("public.users", vec![column("id", "integer", true), column("email", "text", false)]),
+            orders,
+        ]);
+
+        let diff = diff(&from, &to);
+        assert_eq!(render(&diff.up),
+            "CREATE TABLE public.orders (\n    id integer NOT NULL,\n    user_id integer NOT NULL\n);\n\
+            ALTER TABLE public.users ADD COLUMN email text;\n\
+            ALTER TABLE public.orders ADD CONSTRAINT orders_user_id_fkey FOREIGN KEY (user_id) REFERENCES users(id);\n"
+        );
+        assert_eq!(render(&diff.down),
+            "DROP TABLE public.orders;\nALTER TABLE public.users DROP COLUMN email;\n"
+        );
+        assert_eq!(diff.review_count(), 0);
+    }
+
+    #[test]
+    fn test_ambiguous_changes_need_review() {
+        let from = schema(vec![table("public.users", vec![column("id", "integer", true), column("name", "text", false)])]);
+        let to = schema(vec![table("public.users", vec![column("id", "bigint", true), column("full_name", "text", false)])]);
+
+        let diff = diff(&from, &to);
+        assert_eq!(render(&diff.up),
+            "-- REVIEW: type of public.users.id changes from integer to bigint; check the USING conversion\n\
+            ALTER TABLE public.users ALTER COLUMN id TYPE bigint USING id::bigint;\n\
+            -- REVIEW: public.users.name may have been renamed to full_name; consider ALTER TABLE public.users RENAME COLUMN name TO full_name\n\
+            ALTER TABLE public.users DROP COLUMN name;\n\
+            ALTER TABLE public.users ADD COLUMN full_name text;\n"
+        );
+        assert_eq!(diff.review_count(), 4);
+    }
+
+    #[test]
+    fn test_describe() {
+        let from = schema(vec![table("public.users", vec![column("id", "integer", true)])]);
+        let to = schema(vec![table("public.users", vec![column("id", "integer", false)]), table("public.orders", vec![])]);
+        assert_eq!(describe(&from, &to), vec![
+            "table public.orders added",
+            "table public.users: column id changed from `id integer NOT NULL` to `id integer`",
+        ]);
+        assert!(describe(&to, &to).is_empty());
+    }
+}
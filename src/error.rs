@@ -0,0 +1,109 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum MigrateError {
+    Postgres(postgres::Error),
+    Io(io::Error),
+    UndefinedPlaceholder(String),
+    /// The migrations replayed on the shadow database do not produce the database's schema.
+    ShadowMismatch(Vec<String>),
+    /// Tenant schemas whose migrations failed, with the error of each.
+    TenantFailures(Vec<(String, String)>),
+    /// No migration has the requested revision.
+    UnknownRevision(String),
+    /// A revision prefix or name matches more than one migration.
+    AmbiguousRevision { reference: String, matches: Vec<String> },
+    /// No module with this name is configured.
+    UnknownModule(String),
+    /// `--target` is neither a configured target or group nor a database URL.
+    UnknownTarget(String),
+    /// The database is already at head, or at base, in the requested direction.
+    NothingToMigrate(String),
+    /// Pending migrations sort before the current revision and out-of-order mode is off.
+    OutOfOrder(Vec<String>),
+    /// A migration depends on a revision that is not applied.
+    MissingDependency { revision: String, dependency: String },
+    /// A migration cannot be downgraded while applied migrations depend on it.
+    RequiredBy { revision: String, dependents: Vec<String> },
+    /// Only some of the migrations replaced by a squashed migration are applied.
+    PartiallySquashed { revision: String, pending: Vec<String> },
+    /// A migration cannot be rebased onto itself or one of its descendants.
+    CyclicRebase { revision: String, onto: String },
+    /// Another tool's migrations cannot be imported as they are.
+    ImportRefused(String),
+    /// The export output already exists and is not an empty directory.
+    OutputExists(String),
+    /// The migrations end in more than one head, so they have no single order.
+    MultipleHeads(Vec<String>),
+    /// The range given to `squash` is not a straight line of migrations.
+    InvalidSquashRange(String),
+    /// A tenant schema is read before it has bookkeeping tables.
+    UninitialisedTenant(String),
+}
+
+impl fmt::Display for MigrateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrateError::Postgres(e) => match e.as_db_error() {
+                Some(db_error) => write!(f, "{}", db_error),
+                None => write!(f, "{}", e),
+            },
+            MigrateError::Io(e) => write!(f, "{}", e),
+            MigrateError::UndefinedPlaceholder(name) => write!(f, "Undefined placeholder: ${{{}}}", name),
+            MigrateError::UnknownRevision(revision) => write!(f, "Unknown revision: {}", revision),
+            MigrateError::AmbiguousRevision { reference, matches } => {
+                write!(f, "Revision {} is ambiguous, it matches {}", reference, matches.join(", "))
+            }
+            MigrateError::UnknownModule(module) => write!(f, "Unknown module: {}", module),
+            MigrateError::UnknownTarget(target) => write!(f, "Unknown target: {}", target),
+            MigrateError::NothingToMigrate(reason) => write!(f, "Nothing to migrate: {}", reason),
+            MigrateError::OutOfOrder(revisions) => {
+                write!(f, "Pending migrations older than the current revision: {}", revisions.join(", "))
+            }
+            MigrateError::MissingDependency { revision, dependency } => {
+                write!(f, "Migration {} depends on {}, which is not applied", revision, dependency)
+            }
+            MigrateError::RequiredBy { revision, dependents } => {
+                write!(f, "Migration {} is still required by {}", revision, dependents.join(", "))
+            }
+            MigrateError::PartiallySquashed { revision, pending } => write!(
+                f,
+                "Migration {} squashes {}, which are not applied; upgrade past them with the original migrations first",
+                revision,
+                pending.join(", ")
+            ),
+            MigrateError::CyclicRebase { revision, onto } => {
+                write!(f, "Cannot rebase {} onto {}, which is not older than it", revision, onto)
+            }
+            MigrateError::ImportRefused(reason) => write!(f, "Cannot import: {}", reason),
+            MigrateError::OutputExists(output) => write!(f, "Cannot export to {}, it already exists", output),
+            MigrateError::MultipleHeads(heads) => {
+                write!(f, "Found {} heads: {}; merge them before exporting", heads.len(), heads.join(", "))
+            }
+            MigrateError::InvalidSquashRange(reason) => write!(f, "Cannot squash: {}", reason),
+            MigrateError::UninitialisedTenant(schema) => write!(f, "Tenant {} is uninitialised, upgrade it first", schema),
+            MigrateError::TenantFailures(failures) => {
+                let failures: Vec<String> = failures.iter().map(|(tenant, error)| format!("{} ({})", tenant, error)).collect();
+                write!(f, "{} tenant(s) failed: {}", failures.len(), failures.join("; "))
+            }
+            MigrateError::ShadowMismatch(differences) => {
+                write!(f, "Shadow database validation failed: {}", differences.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrateError {}
+
+impl From<postgres::Error> for MigrateError {
+    fn from(e: postgres::Error) -> Self {
+        MigrateError::Postgres(e)
+    }
+}
+
+impl From<io::Error> for MigrateError {
+    fn from(e: io::Error) -> Self {
+        MigrateError::Io(e)
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::check::heads;
+use crate::error::MigrateError;
+use crate::migration::{load_migrations, load_repeatable_migrations, strip_headers};
+use crate::naming::file_name;
+use crate::placeholder;
+
+/// A layout migrations can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `V<n>__<name>.sql`, `U<n>__<name>.sql` undo scripts and `R__<name>.sql` repeatable scripts.
+    Flyway,
+    /// `<nnnn>_<name>.up.sql` and `<nnnn>_<name>.down.sql`. Repeatable scripts follow as migrations
+    /// with an empty down script, since sqlx cannot run a script again.
+    Sqlx,
+    /// A single script running every up migration in order.
+    PlainSql,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flyway" => Ok(Format::Flyway),
+            "sqlx" => Ok(Format::Sqlx),
+            "plain-sql" => Ok(Format::PlainSql),
+            _ => Err(format!("unknown format `{}`, expected flyway, sqlx or plain-sql", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Flyway => "flyway",
+            Format::Sqlx => "sqlx",
+            Format::PlainSql => "plain-sql",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A migration ready to be exported, with its headers stripped and placeholders expanded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedMigration {
+    pub revision: String,
+    pub name: String,
+    pub up_sql: String,
+    pub down_sql: String,
+}
+
+/// Exports the migrations in `dir`, parents first, with their headers stripped and placeholders
+/// expanded with `vars`. Needs no database.
+///
+/// Refuses an output that exists and is not an empty directory, and migrations with more than one
+/// head, which have no order to number them in.
+pub fn export(dir: &Path, format: Format, output: &Path, vars: &BTreeMap<String, String>) -> Result<(), MigrateError> {
+    let occupied = output.is_file() || (output.is_dir() && fs::read_dir(output)?.next().is_some());
+    if occupied {
+        return Err(MigrateError::OutputExists(output.display().to_string()));
+    }
+    let migrations = load_migrations(dir)?;
+    let heads = heads(&migrations);
+    if heads.len() > 1 {
+        return Err(MigrateError::MultipleHeads(heads.iter().map(|m| m.revision.clone()).collect()));
+    }
+
+    let mut exported = Vec::new();
+    for migration in migrations {
+        exported.push(ExportedMigration {
+            revision: migration.revision.clone(),
+            name: migration.name.clone(),
+            up_sql: placeholder::expand(strip_headers(&migration.up_sql()?), vars)?,
+            down_sql: placeholder::expand(strip_headers(&migration.down_sql()?), vars)?,
+        });
+    }
+    let mut repeatable = Vec::new();
+    for migration in load_repeatable_migrations(dir)? {
+        repeatable.push((migration.name.clone(), placeholder::expand(&migration.sql()?, vars)?));
+    }
+
+    write(format, output, &exported, &repeatable)?;
+    println!("Exported {} migrations as {} to {}", exported.len(), format, output.display());
+    Ok(())
+}
+
+/// Writes `migrations`, in order, and the repeatable scripts to `output`, a directory or for
+/// `PlainSql` a file, and returns the files written.
+pub fn write(format: Format, output: &Path, migrations: &[ExportedMigration], repeatable: &[(String, String)]) -> io::Result<Vec<PathBuf>> {
+    if format == Format::PlainSql {
+        let mut script = String::new();
+        for migration in migrations {
+            script.push_str(&format!("-- {} {}\n{}\n", migration.revision, migration.name, migration.up_sql.trim_end()));
+            script.push('\n');
+        }
+        for (name, sql) in repeatable {
+            script.push_str(&format!("-- repeatable {}\n{}\n\n", name, sql.trim_end()));
+        }
+        if let Some(parent) = output.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(output, script)?;
+        return Ok(vec![output.to_path_buf()]);
+    }
+
+    fs::create_dir_all(output)?;
+    let mut files: Vec<(PathBuf, &str)> = Vec::new();
+    for (index, migration) in migrations.iter().enumerate() {
+        let version = index + 1;
+        let name = file_name(&migration.name);
+        match format {
+            Format::Flyway => {
+                files.push((output.join(format!("V{}__{}.sql", version, name)), &migration.up_sql));
+                if !migration.down_sql.trim().is_empty() {
+                    files.push((output.join(format!("U{}__{}.sql", version, name)), &migration.down_sql));
+                }
+            }
+            _ => {
+                files.push((output.join(format!("{:04}_{}.up.sql", version, name)), &migration.up_sql));
+                files.push((output.join(format!("{:04}_{}.down.sql", version, name)), &migration.down_sql));
+            }
+        }
+    }
+    for (index, (name, sql)) in repeatable.iter().enumerate() {
+        let name = file_name(name.trim_end_matches(".sql"));
+        match format {
+            Format::Flyway => files.push((output.join(format!("R__{}.sql", name)), sql)),
+            _ => {
+                let version = migrations.len() + index + 1;
+                files.push((output.join(format!("{:04}_{}.up.sql", version, name)), sql));
+                files.push((output.join(format!("{:04}_{}.down.sql", version, name)), ""));
+            }
+        }
+    }
+
+    for (path, sql) in &files {
+        fs::write(path, sql)?;
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::{write_migration, TempDir};
+
+    fn migrations() -> Vec<ExportedMigration> {
+        vec![
+            ExportedMigration {
+                revision: "ab12".to_string(),
+                name: "add users".to_string(),
+                up_sql: "CREATE TABLE users (id INT);\n".to_string(),
+                down_sql: "DROP TABLE users;\n".to_string(),
+            },
+            ExportedMigration {
+                revision: "cd34".to_string(),
+                name: "add_orders".to_string(),
+                up_sql: "CREATE TABLE orders (id INT);\n".to_string(),
+                down_sql: String::new(),
+            },
+        ]
+    }
+
+    fn file_names(files: &[PathBuf]) -> Vec<String> {
+        files.iter().map(|file| file.file_name().unwrap().to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn test_write_flyway_and_sqlx() {
+        let dir = TempDir::new();
+        let repeatable = vec![("user_view.sql".to_string(), "CREATE OR REPLACE VIEW v AS SELECT 1;\n".to_string())];
+
+        let flyway = write(Format::Flyway, &dir.join("flyway"), &migrations(), &repeatable).unwrap();
+        assert_eq!(file_names(&flyway), vec!["V1__add_users.sql", "U1__add_users.sql", "V2__add_orders.sql", "R__user_view.sql"]);
+        assert_eq!(fs::read_to_string(&flyway[1]).unwrap(), "DROP TABLE users;\n");
+
+        let sqlx = write(Format::Sqlx, &dir.join("sqlx"), &migrations(), &repeatable).unwrap();
+        assert_eq!(file_names(&sqlx), vec![
+            "0001_add_users.up.sql", "0001_add_users.down.sql", "0002_add_orders.up.sql", "0002_add_orders.down.sql",
+            "0003_user_view.up.sql", "0003_user_view.down.sql",
+        ]);
+        assert_eq!(fs::read_to_string(&sqlx[4]).unwrap(), "CREATE OR REPLACE VIEW v AS SELECT 1;\n");
+        assert_eq!(fs::read_to_string(&sqlx[5]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_export() {
+        let output = TempDir::new();
+        let script = output.join("schema.sql");
+        export(Path::new("migrations"), Format::PlainSql, &script, &BTreeMap::new()).unwrap();
+        let sql = fs::read_to_string(&script).unwrap();
+        assert!(sql.starts_with("-- f44e620f-60e0-4470-8904-44b4022b11a5 add users\nCREATE TABLE users ("));
+        assert!(sql.find("CREATE TABLE users").unwrap() < sql.find("wallets").unwrap());
+        assert!(!sql.contains("-- Revision:"));
+
+        let flyway = output.join("flyway");
+        export(Path::new("migrations"), Format::Flyway, &flyway, &BTreeMap::new()).unwrap();
+        let mut flyway_files: Vec<String> = fs::read_dir(&flyway).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        flyway_files.sort();
+        assert_eq!(flyway_files, vec!["U1__add_users.sql", "U2__add_wallet.sql", "V1__add_users.sql", "V2__add_wallet.sql"]);
+
+        let again = export(Path::new("migrations"), Format::PlainSql, &script, &BTreeMap::new());
+        assert!(matches!(again, Err(MigrateError::OutputExists(path)) if path == script.display().to_string()));
+    }
+
+    #[test]
+    fn test_export_multiple_heads() {
+        let dir = TempDir::new();
+        write_migration(&dir, "a", None, "users", "", "");
+        write_migration(&dir, "b", Some("a"), "wallets", "", "");
+        write_migration(&dir, "c", Some("a"), "orders", "", "");
+        let output = TempDir::new();
+        let exported = export(&dir, Format::Sqlx, &output.join("sqlx"), &BTreeMap::new());
+        assert!(matches!(exported, Err(MigrateError::MultipleHeads(heads)) if heads == vec!["b", "c"]));
+        assert!(!output.join("sqlx").exists());
+    }
+
+    #[test]
+    fn test_write_plain_sql() {
+        let dir = TempDir::new();
+        let script = dir.join("schema.sql");
+        write(Format::PlainSql, &script, &migrations(), &[]).unwrap();
+        assert_eq!(
+            fs::read_to_string(&script).unwrap(),
+            "-- ab12 add users\nCREATE TABLE users (id INT);\n\n-- cd34 add_orders\nCREATE TABLE orders (id INT);\n\n"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! ANSI colors for SQL printed to a terminal.
+
+const KEYWORDS: &[&str] = &[
+    "ADD", "ALTER", "AND", "AS", "BEGIN", "BY", "CASCADE", "CHECK", "COLUMN", "COMMIT", "CONSTRAINT", "CREATE",
+    "DEFAULT", "DELETE", "DROP", "EXISTS", "EXTENSION", "FOREIGN", "FROM", "FUNCTION", "GRANT", "IF", "INDEX",
+    "INSERT", "INTO", "KEY", "NOT", "NULL", "ON", "OR", "PRIMARY", "REFERENCES", "RENAME", "REPLACE", "RETURNS",
+    "SCHEMA", "SELECT", "SEQUENCE", "SET", "TABLE", "TO", "TRIGGER", "TYPE", "UNIQUE", "UPDATE", "USING",
+    "VALUES", "VIEW", "WHERE", "WITH",
+];
+
+const RESET: &str = "\x1b[0m";
+const KEYWORD: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const COMMENT: &str = "\x1b[90m";
+
+/// `sql` with keywords, strings, numbers and `--` comments colored.
+pub fn highlight_sql(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut highlighted = String::with_capacity(sql.len() * 2);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let color = if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            Some(COMMENT)
+        } else if c == '\'' {
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == '\'' && chars.get(i + 1) != Some(&'\'') {
+                    break;
+                }
+                i += if chars[i] == '\'' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            Some(STRING)
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            Some(NUMBER)
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            KEYWORDS.contains(&word.to_uppercase().as_str()).then_some(KEYWORD)
+        } else {
+            i += 1;
+            None
+        };
+
+        let token: String = chars[start..i].iter().collect();
+        match color {
+            Some(color) => highlighted.push_str(&format!("{}{}{}", color, token, RESET)),
+            None => highlighted.push_str(&token),
+        }
+    }
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_sql() {
+        assert_eq!(
+            highlight_sql("-- note\ncreate table t (n int DEFAULT 1, s text DEFAULT 'it''s');"),
+            "\x1b[90m-- note\x1b[0m\n\x1b[1;34mcreate\x1b[0m \x1b[1;34mtable\x1b[0m t (n int \x1b[1;34mDEFAULT\x1b[0m \
+            \x1b[33m1\x1b[0m, s text \x1b[1;34mDEFAULT\x1b[0m \x1b[32m'it''s'\x1b[0m);"
+        );
+    }
+}
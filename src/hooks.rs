@@ -0,0 +1,66 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use postgres::Client;
+use crate::error::MigrateError;
+use crate::migration::ordered_files;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    BeforeAll,
+    AfterAll,
+    BeforeEach,
+    AfterEach,
+    OnError,
+}
+
+impl HookPoint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookPoint::BeforeAll => "before_all",
+            HookPoint::AfterAll => "after_all",
+            HookPoint::BeforeEach => "before_each",
+            HookPoint::AfterEach => "after_each",
+            HookPoint::OnError => "on_error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upgrade,
+    Downgrade,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Upgrade => write!(f, "upgrade"),
+            Direction::Downgrade => write!(f, "downgrade"),
+        }
+    }
+}
+
+/// What a hook is being run for.
+///
+/// `revision` is the current revision for `before_all`, `after_all` and `on_error`, and the
+/// migration being applied for `before_each` and `after_each`.
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    pub point: HookPoint,
+    pub direction: Direction,
+    pub revision: Option<String>,
+    pub error: Option<String>,
+}
+
+/// A hook registered from Rust with `DbClient::add_hook`.
+pub type Hook = Box<dyn FnMut(&mut Client, &HookContext) -> Result<(), MigrateError> + Send>;
+
+/// SQL hook files for `point` in `<dir>/hooks`: `<point>.sql` followed by `<point>.<part>.sql`.
+pub fn load_sql_hooks(dir: &Path, point: HookPoint) -> io::Result<Vec<PathBuf>> {
+    let hooks_dir = dir.join("hooks");
+    if !hooks_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    ordered_files(&hooks_dir, point.name())
+}
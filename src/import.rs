@@ -0,0 +1,492 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use postgres::{Client, Error};
+use crate::error::MigrateError;
+use crate::migration::{load_migrations, write_migration};
+use crate::naming::{file_name, MigrationName};
+
+/// A migration tool whose migrations can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// `<version>_<description>.up.sql` / `.down.sql`, or a single `<version>_<description>.sql`.
+    Sqlx,
+    /// `<version>_<name>/up.sql` and `down.sql`.
+    Diesel,
+    /// `V<version>__<name>.sql`, up only.
+    Refinery,
+    /// `V<version>__<description>.sql`, with optional `U<version>__` undo and `R__` repeatable scripts.
+    Flyway,
+    /// Python revision files, of which only the SQL passed to `op.execute` is converted.
+    Alembic,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sqlx" => Ok(Source::Sqlx),
+            "diesel" => Ok(Source::Diesel),
+            "refinery" => Ok(Source::Refinery),
+            "flyway" => Ok(Source::Flyway),
+            "alembic" => Ok(Source::Alembic),
+            _ => Err(format!("unknown tool `{}`, expected sqlx, diesel, refinery, flyway or alembic", s)),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Source::Sqlx => "sqlx",
+            Source::Diesel => "diesel",
+            Source::Refinery => "refinery",
+            Source::Flyway => "flyway",
+            Source::Alembic => "alembic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A migration read from another tool, ready to be written as a pg_migrate file pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedMigration {
+    /// The version the other tool records in its bookkeeping table.
+    pub revision: String,
+    pub name: String,
+    pub down_revisions: Vec<String>,
+    pub up_sql: String,
+    pub down_sql: String,
+}
+
+/// Everything read from another tool's migration directory.
+#[derive(Debug, Default)]
+pub struct Import {
+    /// Migrations parents first.
+    pub migrations: Vec<ImportedMigration>,
+    /// Repeatable scripts as file name and SQL.
+    pub repeatable: Vec<(String, String)>,
+}
+
+/// Converts the migrations of `source` in `dir` into `migration_dir` and returns them, or `None`
+/// when there is nothing to import. Needs no database; see `DbClient::mark_imported`.
+///
+/// Running it again skips the migrations that were already imported.
+pub fn convert(source: Source, dir: &Path, migration_dir: &Path) -> Result<Option<Import>, MigrateError> {
+    if migration_dir.exists() && fs::canonicalize(dir)? == fs::canonicalize(migration_dir)? {
+        return Err(MigrateError::ImportRefused(format!("{} is MIGRATION_DIR itself, choose another MIGRATION_DIR", dir.display())));
+    }
+    let import = read_migrations(source, dir)?;
+    if import.migrations.is_empty() {
+        println!("No {} migrations found in {}", source, dir.display());
+        return Ok(None);
+    }
+    let existing = if migration_dir.exists() { load_migrations(migration_dir)? } else { Vec::new() };
+    if let Some(migration) = existing.iter().find(|m| !import.migrations.iter().any(|i| i.revision == m.revision)) {
+        return Err(MigrateError::ImportRefused(format!("the migration directory already has migration {}", migration.revision)));
+    }
+
+    fs::create_dir_all(migration_dir)?;
+    let mut imported = 0;
+    for migration in &import.migrations {
+        if existing.iter().any(|m| m.revision == migration.revision) {
+            continue;
+        }
+        let migration_name = MigrationName {
+            revision: migration.revision.clone(),
+            stem: format!("{}_{}", migration.revision, file_name(&migration.name)),
+        };
+        let down_revision = if migration.down_revisions.is_empty() { "None".to_string() } else { migration.down_revisions.join(", ") };
+        write_migration(migration_dir, &migration_name, &migration.name, &[("Down Revision", down_revision)], &migration.up_sql, &migration.down_sql)?;
+        imported += 1;
+    }
+    for (name, sql) in &import.repeatable {
+        let path = migration_dir.join("repeatable").join(name);
+        if !path.exists() {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, sql)?;
+        }
+    }
+    println!("Imported {} {} migrations", imported, source);
+    Ok(Some(import))
+}
+
+/// Reads the migrations of `source` in `dir`.
+pub fn read_migrations(source: Source, dir: &Path) -> Result<Import, MigrateError> {
+    let mut import = Import::default();
+    let mut versioned: Vec<(Vec<u64>, ImportedMigration)> = Vec::new();
+    for path in sorted_entries(dir)? {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        match source {
+            Source::Sqlx => {
+                if let Some(stem) = file_name.strip_suffix(".up.sql") {
+                    let down_file = path.with_file_name(format!("{}.down.sql", stem));
+                    let down_sql = if down_file.is_file() { fs::read_to_string(down_file)? } else { String::new() };
+                    push_versioned(&mut versioned, source, stem, fs::read_to_string(&path)?, down_sql);
+                } else if file_name.ends_with(".sql") && !file_name.ends_with(".down.sql") {
+                    push_versioned(&mut versioned, source, file_name.trim_end_matches(".sql"), fs::read_to_string(&path)?, String::new());
+                }
+            }
+            Source::Diesel => {
+                if path.join("up.sql").is_file() {
+                    let down_file = path.join("down.sql");
+                    let down_sql = if down_file.is_file() { fs::read_to_string(down_file)? } else { String::new() };
+                    push_versioned(&mut versioned, source, &file_name, fs::read_to_string(path.join("up.sql"))?, down_sql);
+                }
+            }
+            Source::Refinery => {
+                let Some(stem) = file_name.strip_suffix(".sql") else {
+                    continue;
+                };
+                if let Some(stem) = stem.strip_prefix('V') {
+                    push_versioned(&mut versioned, source, stem, fs::read_to_string(&path)?, String::new());
+                } else if stem.starts_with('U') && stem.contains("__") {
+                    // Unversioned refinery migrations run outside the version order, which a revision chain cannot express.
+                    return Err(MigrateError::ImportRefused(format!("{} is an unversioned refinery migration", file_name)));
+                }
+            }
+            Source::Flyway => {
+                let Some(stem) = file_name.strip_suffix(".sql") else {
+                    continue;
+                };
+                if let Some(description) = stem.strip_prefix("R__") {
+                    import.repeatable.push((format!("{}.sql", description), fs::read_to_string(&path)?));
+                } else if let Some(stem) = stem.strip_prefix('V') {
+                    let down_sql = match sorted_entries(dir)?.into_iter().find(|undo| is_flyway_undo(undo, stem)) {
+                        Some(undo) => fs::read_to_string(undo)?,
+                        None => String::new(),
+                    };
+                    push_versioned(&mut versioned, source, stem, fs::read_to_string(&path)?, down_sql);
+                }
+            }
+            Source::Alembic => {
+                if file_name.ends_with(".py") {
+                    if let Some(migration) = read_alembic(&path)? {
+                        import.migrations.push(migration);
+                    }
+                }
+            }
+        }
+    }
+
+    if source == Source::Alembic {
+        import.migrations = order_by_parents(import.migrations);
+        return Ok(import);
+    }
+    versioned.sort_by(|(a, _), (b, _)| compare_versions(a, b));
+    let mut parent: Option<String> = None;
+    for (_, mut migration) in versioned {
+        migration.down_revisions = parent.into_iter().collect();
+        parent = Some(migration.revision.clone());
+        import.migrations.push(migration);
+    }
+    Ok(import)
+}
+
+/// Revisions that `source` recorded as applied, parents first; empty when its table does not exist.
+pub fn applied_revisions(source: Source, client: &mut Client, migrations: &[ImportedMigration]) -> Result<Vec<String>, Error> {
+    let (table, query) = match source {
+        Source::Sqlx => ("_sqlx_migrations", "SELECT version::TEXT FROM _sqlx_migrations WHERE success"),
+        Source::Diesel => ("__diesel_schema_migrations", "SELECT version::TEXT FROM __diesel_schema_migrations"),
+        Source::Refinery => ("refinery_schema_history", "SELECT version::TEXT FROM refinery_schema_history"),
+        Source::Flyway => (
+            "flyway_schema_history",
+            "SELECT version FROM flyway_schema_history WHERE success AND version IS NOT NULL AND type <> 'DELETE'",
+        ),
+        Source::Alembic => ("alembic_version", "SELECT version_num::TEXT FROM alembic_version"),
+    };
+    let exists: Option<String> = client.query_one("SELECT to_regclass($1)::TEXT", &[&table])?.get(0);
+    if exists.is_none() {
+        return Ok(Vec::new());
+    }
+    let recorded: Vec<String> = client.query(query, &[])?.iter().map(|row| row.get(0)).collect();
+
+    // alembic_version only holds the heads; everything they descend from is applied too.
+    let mut applied: Vec<String> = recorded;
+    if source == Source::Alembic {
+        let mut index = 0;
+        while index < applied.len() {
+            if let Some(migration) = migrations.iter().find(|m| m.revision == applied[index]) {
+                for parent in &migration.down_revisions {
+                    if !applied.contains(parent) {
+                        applied.push(parent.clone());
+                    }
+                }
+            }
+            index += 1;
+        }
+    }
+    Ok(migrations.iter().map(|m| m.revision.clone()).filter(|revision| applied.contains(revision)).collect())
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>, MigrateError> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Adds `<version><separator><name>` unless the version is not numeric.
+fn push_versioned(versioned: &mut Vec<(Vec<u64>, ImportedMigration)>, source: Source, stem: &str, up_sql: String, down_sql: String) {
+    let separator = if matches!(source, Source::Sqlx | Source::Diesel) { "_" } else { "__" };
+    let (version, name) = stem.split_once(separator).unwrap_or((stem, ""));
+    let Some(parts) = version_parts(version) else {
+        return;
+    };
+    // The version as the tool stores it in its bookkeeping table.
+    let revision = match source {
+        Source::Sqlx | Source::Refinery => parts[0].to_string(),
+        Source::Diesel => version.replace('-', ""),
+        _ => version.replace('_', "."),
+    };
+    let name = if name.is_empty() { revision.clone() } else { name.to_string() };
+    let migration = ImportedMigration { revision, name, down_revisions: Vec::new(), up_sql, down_sql };
+    versioned.push((parts, migration));
+}
+
+/// The numeric parts of a version such as `1.2`, `1_2`, `20240101120000` or `2024-01-01-120000`.
+fn version_parts(version: &str) -> Option<Vec<u64>> {
+    if version.is_empty() {
+        return None;
+    }
+    let digits = version.replace('-', "");
+    digits.split(['.', '_']).map(|part| part.parse().ok()).collect()
+}
+
+fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
+    let length = a.len().max(b.len());
+    (0..length).map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+fn is_flyway_undo(path: &Path, stem: &str) -> bool {
+    let version = stem.split_once("__").map_or(stem, |(version, _)| version);
+    path.file_name().unwrap_or_default().to_string_lossy().starts_with(&format!("U{}__", version))
+}
+
+/// Orders alembic revisions so that every revision comes after its down revisions.
+fn order_by_parents(mut remaining: Vec<ImportedMigration>) -> Vec<ImportedMigration> {
+    let mut ordered: Vec<ImportedMigration> = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining.iter()
+            .position(|migration| migration.down_revisions.iter().all(|parent| !remaining.iter().any(|m| &m.revision == parent)));
+        ordered.push(remaining.remove(ready.unwrap_or(0)));
+    }
+    ordered
+}
+
+fn read_alembic(path: &Path) -> Result<Option<ImportedMigration>, MigrateError> {
+    let source = fs::read_to_string(path)?;
+    let Some(revision) = python_assignment(&source, "revision").and_then(|value| python_strings(&value).into_iter().next()) else {
+        return Ok(None);
+    };
+    let down_revisions = python_assignment(&source, "down_revision").map(|value| python_strings(&value)).unwrap_or_default();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let name = stem.strip_prefix(&format!("{}_", revision)).unwrap_or(&stem).to_string();
+
+    Ok(Some(ImportedMigration {
+        revision,
+        name,
+        down_revisions,
+        up_sql: convert_operations(python_function(&source, "upgrade")),
+        down_sql: convert_operations(python_function(&source, "downgrade")),
+    }))
+}
+
+/// The right-hand side of a top-level `name = ...` or `name: type = ...` assignment.
+fn python_assignment(source: &str, name: &str) -> Option<String> {
+    source.lines().find_map(|line| {
+        let rest = line.strip_prefix(name)?;
+        let rest = rest.trim_start();
+        let rest = match rest.strip_prefix(':') {
+            Some(annotated) => annotated.split_once('=')?.1,
+            None => rest.strip_prefix('=')?,
+        };
+        Some(rest.trim().to_string())
+    })
+}
+
+/// The quoted strings in a Python value, e.g. `'ab12'` or `('ab12', 'cd34')`; `None` gives none.
+fn python_strings(value: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find(['\'', '"']) {
+        let quote = rest.as_bytes()[start] as char;
+        let Some(length) = rest[start + 1..].find(quote) else {
+            break;
+        };
+        strings.push(rest[start + 1..start + 1 + length].to_string());
+        rest = &rest[start + length + 2..];
+    }
+    strings
+}
+
+/// The body of a top-level `def name(...)`, up to the next top-level statement.
+fn python_function<'a>(source: &'a str, name: &str) -> &'a str {
+    let Some(start) = source.find(&format!("def {}(", name)) else {
+        return "";
+    };
+    let body = &source[start..];
+    let body = body.split_once('\n').map_or("", |(_, body)| body);
+    let end = body.match_indices('\n')
+        .map(|(i, _)| i + 1)
+        .find(|&i| body[i..].starts_with(|c: char| !c.is_whitespace()))
+        .unwrap_or(body.len());
+    &body[..end]
+}
+
+/// Converts the `op.execute` calls of an alembic function to SQL; other operations are kept as
+/// `-- REVIEW:` comments to be converted by hand.
+fn convert_operations(body: &str) -> String {
+    let mut sql = String::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("op.") {
+        let call = &rest[start..];
+        if let Some(arguments) = call.strip_prefix("op.execute(") {
+            let arguments = arguments.trim_start();
+            let arguments = arguments.strip_prefix("sa.text(").or_else(|| arguments.strip_prefix("text(")).unwrap_or(arguments);
+            if let Some((literal, remaining)) = python_literal(arguments.trim_start()) {
+                let statement = literal.trim();
+                sql.push_str(statement);
+                if !statement.ends_with(';') {
+                    sql.push(';');
+                }
+                sql.push('\n');
+                rest = remaining;
+                continue;
+            }
+        }
+        let line = call.lines().next().unwrap_or_default().trim();
+        sql.push_str(&format!("-- REVIEW: alembic operation not converted: {}\n", line));
+        rest = &call[line.len()..];
+    }
+    sql
+}
+
+/// A string literal at the start of `source` and the source after it.
+fn python_literal(source: &str) -> Option<(&str, &str)> {
+    let source = source.strip_prefix(['r', 'R']).unwrap_or(source);
+    for quote in ["\"\"\"", "'''", "\"", "'"] {
+        if let Some(literal) = source.strip_prefix(quote) {
+            let end = literal.find(quote)?;
+            return Some((&literal[..end], &literal[end + quote.len()..]));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::TempDir;
+
+    fn revisions(import: &Import) -> Vec<(&str, &str, Vec<&str>)> {
+        import.migrations.iter()
+            .map(|m| (m.revision.as_str(), m.name.as_str(), m.down_revisions.iter().map(|s| s.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_sqlx_and_diesel() {
+        let dir = TempDir::new();
+        fs::write(dir.join("20240102090000_add_orders.up.sql"), "CREATE TABLE orders (id INT);\n").unwrap();
+        fs::write(dir.join("20240102090000_add_orders.down.sql"), "DROP TABLE orders;\n").unwrap();
+        fs::write(dir.join("20240101090000_add_users.sql"), "CREATE TABLE users (id INT);\n").unwrap();
+        let sqlx = read_migrations(Source::Sqlx, &dir).unwrap();
+        assert_eq!(revisions(&sqlx), vec![
+            ("20240101090000", "add_users", vec![]),
+            ("20240102090000", "add_orders", vec!["20240101090000"]),
+        ]);
+        assert_eq!(sqlx.migrations[1].down_sql, "DROP TABLE orders;\n");
+
+        let dir = TempDir::new();
+        fs::create_dir(dir.join("2024-01-01-090000_add_users")).unwrap();
+        fs::write(dir.join("2024-01-01-090000_add_users/up.sql"), "CREATE TABLE users (id INT);\n").unwrap();
+        fs::write(dir.join("2024-01-01-090000_add_users/down.sql"), "DROP TABLE users;\n").unwrap();
+        let diesel = read_migrations(Source::Diesel, &dir).unwrap();
+        assert_eq!(revisions(&diesel), vec![("20240101090000", "add_users", vec![])]);
+        assert_eq!(diesel.migrations[0].down_sql, "DROP TABLE users;\n");
+    }
+
+    #[test]
+    fn test_read_flyway_and_refinery() {
+        let dir = TempDir::new();
+        fs::write(dir.join("V1__add_users.sql"), "CREATE TABLE users (id INT);\n").unwrap();
+        fs::write(dir.join("V1_10__add_index.sql"), "CREATE INDEX users_id ON users (id);\n").unwrap();
+        fs::write(dir.join("V1.2__add_orders.sql"), "CREATE TABLE orders (id INT);\n").unwrap();
+        fs::write(dir.join("U1.2__add_orders.sql"), "DROP TABLE orders;\n").unwrap();
+        fs::write(dir.join("R__user_view.sql"), "CREATE OR REPLACE VIEW user_ids AS SELECT id FROM users;\n").unwrap();
+        let flyway = read_migrations(Source::Flyway, &dir).unwrap();
+        assert_eq!(revisions(&flyway), vec![
+            ("1", "add_users", vec![]),
+            ("1.2", "add_orders", vec!["1"]),
+            ("1.10", "add_index", vec!["1.2"]),
+        ]);
+        assert_eq!(flyway.migrations[1].down_sql, "DROP TABLE orders;\n");
+        assert_eq!(flyway.repeatable[0].0, "user_view.sql");
+
+        let dir = TempDir::new();
+        fs::write(dir.join("V2__add_orders.sql"), "CREATE TABLE orders (id INT);\n").unwrap();
+        fs::write(dir.join("V001__add_users.sql"), "CREATE TABLE users (id INT);\n").unwrap();
+        let refinery = read_migrations(Source::Refinery, &dir).unwrap();
+        assert_eq!(revisions(&refinery), vec![("1", "add_users", vec![]), ("2", "add_orders", vec!["1"])]);
+        assert_eq!(refinery.migrations[0].down_sql, "");
+
+        fs::write(dir.join("U3__backfill.sql"), "UPDATE orders SET id = id;\n").unwrap();
+        let unversioned = read_migrations(Source::Refinery, &dir);
+        assert!(matches!(unversioned, Err(MigrateError::ImportRefused(reason)) if reason == "U3__backfill.sql is an unversioned refinery migration"));
+    }
+
+    #[test]
+    fn test_convert() {
+        let dir = TempDir::new();
+        fs::write(dir.join("V1__add_users.sql"), "CREATE TABLE users (id INT);\n").unwrap();
+        fs::write(dir.join("R__user_view.sql"), "CREATE OR REPLACE VIEW user_ids AS SELECT id FROM users;\n").unwrap();
+        let migration_dir = TempDir::new();
+
+        let import = convert(Source::Flyway, &dir, &migration_dir).unwrap();
+        let migrations = load_migrations(&migration_dir).unwrap();
+        let again = convert(Source::Flyway, &dir, &migration_dir).unwrap();
+        let into_itself = convert(Source::Flyway, &dir, &dir);
+
+        assert_eq!(import.unwrap().migrations.len(), 1);
+        assert_eq!(migrations.len(), 1);
+        assert_eq!(migrations[0].revision, "1");
+        assert!(migration_dir.join("1_add_users_up.sql").is_file());
+        assert!(migration_dir.join("repeatable").join("user_view.sql").is_file());
+        assert!(again.is_some());
+        assert!(matches!(into_itself, Err(MigrateError::ImportRefused(_))));
+
+        let other = TempDir::new();
+        fs::write(other.join("V2__add_orders.sql"), "CREATE TABLE orders (id INT);\n").unwrap();
+        let conflicting = convert(Source::Flyway, &other, &migration_dir);
+        assert!(matches!(conflicting, Err(MigrateError::ImportRefused(reason)) if reason == "the migration directory already has migration 1"));
+        assert!(convert(Source::Flyway, &TempDir::new(), &migration_dir).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_alembic() {
+        let dir = TempDir::new();
+        fs::write(dir.join("ab12_add_users.py"), "\"\"\"add users\"\"\"\nfrom alembic import op\n\n\
+            revision = 'ab12'\ndown_revision = None\n\n\
+            def upgrade():\n    op.execute(\"CREATE TABLE users (id INT)\")\n\n\
+            def downgrade():\n    op.drop_table('users')\n").unwrap();
+        fs::write(dir.join("cd34_add_orders.py"), "from alembic import op\nimport sqlalchemy as sa\n\n\
+            revision: str = \"cd34\"\ndown_revision: Union[str, None] = \"ab12\"\n\n\
+            def upgrade() -> None:\n    op.execute(sa.text(\"\"\"\n        CREATE TABLE orders (id INT);\n    \"\"\"))\n\n\
+            def downgrade() -> None:\n    op.execute('DROP TABLE orders')\n").unwrap();
+
+        let alembic = read_migrations(Source::Alembic, &dir).unwrap();
+        assert_eq!(revisions(&alembic), vec![("ab12", "add_users", vec![]), ("cd34", "add_orders", vec!["ab12"])]);
+        assert_eq!(alembic.migrations[0].up_sql, "CREATE TABLE users (id INT);\n");
+        assert_eq!(alembic.migrations[0].down_sql, "-- REVIEW: alembic operation not converted: op.drop_table('users')\n");
+        assert_eq!(alembic.migrations[1].up_sql, "CREATE TABLE orders (id INT);\n");
+        assert_eq!(alembic.migrations[1].down_sql, "DROP TABLE orders;\n");
+    }
+}
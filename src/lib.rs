@@ -1,25 +1,137 @@
 pub mod setup;
-use std::fs::OpenOptions;
-use std::io::{BufRead, Write};
+pub mod check;
+pub mod config;
+pub mod diff;
+pub mod error;
+pub mod export;
+pub mod highlight;
+pub mod hooks;
+pub mod import;
+pub mod migration;
+pub mod naming;
+pub mod placeholder;
+pub mod revision;
+pub mod schema;
+pub mod scratch;
+pub mod seed;
+pub mod squash;
+pub mod target;
+pub mod tenant;
+pub mod testing;
+#[cfg(feature = "tui")]
+pub mod tui;
+use std::io::IsTerminal;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::string::String;
 use postgres::{Client, NoTls, Error};
-use chrono::Utc;
-use std::fs::File;
-use uuid::Uuid;
 use std::env;
 use dotenv::dotenv;
+use crate::migration::{load_migrations, load_repeatable_migrations, sql_checksum, strip_headers, write_new_migration, Migration};
+use crate::naming::NamingScheme;
+use crate::config::Config;
+use crate::error::MigrateError;
+use crate::hooks::{load_sql_hooks, Direction, Hook, HookContext, HookPoint};
+use crate::export::Format;
+use crate::import::{ImportedMigration, Source};
+use crate::seed::load_seeds;
+use crate::schema::Schema;
+use crate::scratch::ScratchDatabase;
+use crate::tenant::{quote_ident, run_parallel, TenantsConfig};
+use std::collections::BTreeMap;
+
+/// Module of the migrations in `MIGRATION_DIR`.
+pub const DEFAULT_MODULE: &str = "default";
 
 pub struct DbClient {
     client: Client,
+    database_url: String,
+    migration_dir: PathBuf,
+    seed_dir: PathBuf,
+    naming: NamingScheme,
+    vars: BTreeMap<String, String>,
+    dry_run: bool,
+    hooks: Vec<(HookPoint, Hook)>,
+    dump_schema: bool,
+    schema_file: PathBuf,
+    shadow_check: bool,
+    shadow_url: Option<String>,
+    allow_out_of_order: bool,
+    module: String,
+    modules: BTreeMap<String, PathBuf>,
+    tenants: TenantsConfig,
+}
+
+/// The settings a tenant worker needs to open its own client, since `DbClient` is not `Sync`.
+#[derive(Clone)]
+struct TenantClient {
+    database_url: String,
+    migration_dir: PathBuf,
+    module: String,
+    vars: BTreeMap<String, String>,
+    dry_run: bool,
+    allow_out_of_order: bool,
+}
+
+impl TenantClient {
+    /// Connects to `tenant`, creating it when `create` is set, see `DbClient::new_for_tenant`.
+    fn connect(&self, tenant: &str, create: bool) -> Result<DbClient, MigrateError> {
+        let mut client = DbClient::_connect(&self.database_url)?;
+        client.migration_dir = self.migration_dir.clone();
+        client.module = self.module.clone();
+        client.vars = self.vars.clone();
+        client.dry_run = self.dry_run;
+        client.allow_out_of_order = self.allow_out_of_order;
+        client.dump_schema = false;
+        client.shadow_check = false;
+        client._enter_tenant(tenant, create)?;
+        Ok(client)
+    }
 }
 
 impl DbClient {
 
-    pub fn new(database_url: &str) -> Result<Self, Error> {
-        let mut client = Client::connect(database_url, NoTls)?;
+    pub fn new(database_url: &str) -> Result<Self, MigrateError> {
+        let mut db_client = DbClient::_connect(database_url)?;
+        DbClient::_create_bookkeeping(&mut db_client.client)?;
+        Ok(db_client)
+    }
+
+    /// Connects for the tenant in `schema` without creating bookkeeping tables in `public`. With
+    /// `create` the tenant is set up as by `set_tenant`, otherwise it is only read as by `use_tenant`.
+    pub fn new_for_tenant(database_url: &str, schema: &str, create: bool) -> Result<Self, MigrateError> {
+        let mut db_client = DbClient::_connect(database_url)?;
+        db_client._enter_tenant(schema, create)?;
+        Ok(db_client)
+    }
+
+    fn _connect(database_url: &str) -> Result<Self, MigrateError> {
+        let config = Config::load()?;
+        let client = Client::connect(database_url, NoTls)?;
+
+        Ok(DbClient {
+            client,
+            database_url: database_url.to_string(),
+            migration_dir: PathBuf::from(DbClient::get_migration_dir()),
+            seed_dir: PathBuf::from(DbClient::get_seed_dir()),
+            naming: NamingScheme::from_env(),
+            vars: config.placeholder_vars(),
+            dry_run: false,
+            hooks: Vec::new(),
+            dump_schema: config.dump_schema,
+            schema_file: PathBuf::from(config.schema_file.as_deref().unwrap_or("schema.sql")),
+            shadow_check: config.shadow_check,
+            shadow_url: config.shadow_database_url(),
+            allow_out_of_order: config.allow_out_of_order,
+            module: DEFAULT_MODULE.to_string(),
+            modules: config.modules.iter().map(|(name, dir)| (name.clone(), PathBuf::from(dir))).collect(),
+            tenants: config.tenants,
+        })
+    }
+
+    /// Creates the bookkeeping tables in the first schema of the search path.
+    fn _create_bookkeeping(client: &mut Client) -> Result<(), Error> {
         client.batch_execute(
             "CREATE TABLE IF NOT EXISTS migrations (
                 id SERIAL PRIMARY KEY,
@@ -29,100 +141,596 @@ impl DbClient {
         client.batch_execute(
             "CREATE TABLE IF NOT EXISTS history (
                 id SERIAL PRIMARY KEY,
-                migration_id TEXT NOT NULL UNIQUE,
+                migration_id TEXT NOT NULL,
                 name TEXT NOT NULL,
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
         )")?;
 
-        Ok(DbClient { client })
+        client.batch_execute("ALTER TABLE history ADD COLUMN IF NOT EXISTS checksum TEXT")?;
+        client.batch_execute("ALTER TABLE history ADD COLUMN IF NOT EXISTS depends_on TEXT[]")?;
+        client.batch_execute(&format!(
+            "ALTER TABLE migrations ADD COLUMN IF NOT EXISTS module TEXT NOT NULL DEFAULT '{0}';
+            ALTER TABLE history ADD COLUMN IF NOT EXISTS module TEXT NOT NULL DEFAULT '{0}';",
+            DEFAULT_MODULE
+        ))?;
+        // Revisions are unique per module; tables created before modules were unique on `migration_id`.
+        client.batch_execute(
+            "ALTER TABLE history DROP CONSTRAINT IF EXISTS history_migration_id_key;
+            CREATE UNIQUE INDEX IF NOT EXISTS history_module_migration_id_key ON history (module, migration_id);"
+        )?;
+
+        client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS repeatable_migrations (
+                name TEXT PRIMARY KEY,
+                checksum TEXT NOT NULL,
+                applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )")?;
+
+        client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS seeds (
+                name TEXT PRIMARY KEY,
+                env TEXT,
+                revision TEXT,
+                checksum TEXT NOT NULL,
+                applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )")?;
+        Ok(())
+    }
+
+    pub fn set_migration_dir(&mut self, migration_dir: &str) {
+        self.migration_dir = PathBuf::from(migration_dir);
+    }
+
+    pub fn set_seed_dir(&mut self, seed_dir: &str) {
+        self.seed_dir = PathBuf::from(seed_dir);
+    }
+
+    pub fn set_naming_scheme(&mut self, template: &str) {
+        self.naming = NamingScheme::new(template);
+    }
+
+    /// Sets the value of a `${key}` placeholder, overriding the config file and environment.
+    pub fn set_var(&mut self, key: &str, value: &str) {
+        self.vars.insert(key.to_string(), value.to_string());
+    }
+
+    /// When enabled, migrations print their expanded SQL instead of being applied.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// When enabled, the schema snapshot is written after every upgrade.
+    pub fn set_dump_schema(&mut self, dump_schema: bool) {
+        self.dump_schema = dump_schema;
+    }
+
+    pub fn set_schema_file(&mut self, schema_file: &str) {
+        self.schema_file = PathBuf::from(schema_file);
+    }
+
+    /// When enabled, upgrades are refused unless the shadow database validation passes.
+    pub fn set_shadow_check(&mut self, shadow_check: bool) {
+        self.shadow_check = shadow_check;
+    }
+
+    /// Uses `shadow_url` as the shadow database. Its contents are dropped on every validation.
+    pub fn set_shadow_url(&mut self, shadow_url: Option<&str>) {
+        self.shadow_url = shadow_url.map(|url| url.to_string());
+    }
+
+    /// When enabled, pending migrations that sort before the current revision are applied on
+    /// upgrade; otherwise the upgrade is refused while any exist.
+    pub fn set_allow_out_of_order(&mut self, allow_out_of_order: bool) {
+        self.allow_out_of_order = allow_out_of_order;
+    }
+
+    /// Registers a module whose migrations live in `migration_dir`, in addition to the ones in
+    /// `[modules]` of `pg_migrate.toml`.
+    pub fn add_module(&mut self, name: &str, migration_dir: &str) {
+        self.modules.insert(name.to_string(), PathBuf::from(migration_dir));
+    }
+
+    /// Switches to the revision chain of `module`; `default` is the chain in `MIGRATION_DIR`.
+    pub fn set_module(&mut self, module: &str) -> Result<(), MigrateError> {
+        self.migration_dir = if module == DEFAULT_MODULE {
+            PathBuf::from(DbClient::get_migration_dir())
+        } else {
+            self.modules.get(module).cloned().ok_or_else(|| MigrateError::UnknownModule(module.to_string()))?
+        };
+        self.module = module.to_string();
+        Ok(())
+    }
+
+    /// The default module, if its directory exists, followed by the configured modules.
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules = Vec::new();
+        if Path::new(&DbClient::get_migration_dir()).is_dir() {
+            modules.push(DEFAULT_MODULE.to_string());
+        }
+        modules.extend(self.modules.keys().cloned());
+        modules
+    }
+
+    /// Migrates the tenant in `schema`: creates the schema when missing, puts it first on the
+    /// search path and keeps the tenant's revision in bookkeeping tables inside it.
+    pub fn set_tenant(&mut self, schema: &str) -> Result<(), MigrateError> {
+        let schema = quote_ident(schema);
+        self.client.batch_execute(&format!("CREATE SCHEMA IF NOT EXISTS {0}; SET search_path TO {0}, public;", schema))?;
+        DbClient::_create_bookkeeping(&mut self.client)?;
+        Ok(())
+    }
+
+    /// Puts the tenant in `schema` first on the search path without creating anything, for
+    /// commands that only read it. Fails if the tenant has not been migrated yet.
+    pub fn use_tenant(&mut self, schema: &str) -> Result<(), MigrateError> {
+        let table = format!("{}.migrations", quote_ident(schema));
+        let initialised: bool = self.client.query_one("SELECT to_regclass($1) IS NOT NULL", &[&table])?.get(0);
+        if !initialised {
+            return Err(MigrateError::UninitialisedTenant(schema.to_string()));
+        }
+        self.client.batch_execute(&format!("SET search_path TO {}, public;", quote_ident(schema)))?;
+        Ok(())
+    }
+
+    fn _enter_tenant(&mut self, schema: &str, create: bool) -> Result<(), MigrateError> {
+        if create {
+            self.set_tenant(schema)
+        } else {
+            self.use_tenant(schema)
+        }
+    }
+
+    pub fn set_tenants(&mut self, tenants: TenantsConfig) {
+        self.tenants = tenants;
+    }
+
+    /// The tenant schemas from `[tenants]` of `pg_migrate.toml`, or `set_tenants`.
+    pub fn tenant_schemas(&mut self) -> Result<Vec<String>, MigrateError> {
+        Ok(self.tenants.schemas(&mut self.client)?)
+    }
+
+    /// Registers a hook to run at `point`, after the SQL hooks in `<MIGRATION_DIR>/hooks`.
+    pub fn add_hook<F>(&mut self, point: HookPoint, hook: F)
+    where
+        F: FnMut(&mut Client, &HookContext) -> Result<(), MigrateError> + Send + 'static,
+    {
+        self.hooks.push((point, Box::new(hook)));
+    }
+
+    pub fn clear_hooks(&mut self) {
+        self.hooks.clear();
     }
 
     pub fn create_new_migration(&mut self, name: &str) -> io::Result<()> {
-        let migrations_dir = PathBuf::from(DbClient::get_migration_dir());
-        if !migrations_dir.exists() {
-            fs::create_dir(&migrations_dir).unwrap();
-        }
-
-        let head = self._get_head();
-
-        let timestamp = Utc::now().format("%Y-%m-%d_%H:%M:%S").to_string();
-        let uuid = Uuid::new_v4();
-        let up_file = migrations_dir.join(format!("{}_{}_{}_up.sql", timestamp, uuid, name));
-        let down_file = migrations_dir.join(format!("{}_{}_{}_down.sql", timestamp, uuid, name));
-
-        {
-            let mut file = OpenOptions::new()
-                .write(true)
-                .append(true)
-                .create(true)
-                .open(&up_file)
-                .unwrap();
-            writeln!(file, "-- SQL commands to upgrade").unwrap();
-            writeln!(file, "-- Revision: {}", uuid).unwrap();
-            writeln!(file, "-- Down Revision: {}", head.as_deref().unwrap_or("None"))?;
-        }
-
-        {
-            let mut file = OpenOptions::new()
-                .write(true)
-                .append(true)
-                .create(true)
-                .open(&down_file)
-                .unwrap();
-            writeln!(file, "-- SQL commands to downgrade").unwrap();
-            writeln!(file, "-- Revision: {}", uuid).unwrap();
-            writeln!(file, "-- Down Revision: {}", head.as_deref().unwrap_or("None"))?;
-        }
-
-        println!("Created migration: {}_{}", timestamp, name);
+        let stem = self._write_migration(name, "", "")?;
+        println!("Created migration: {}", stem);
         Ok(())
     }
 
-    pub fn run_migrations(&mut self, upgrade: bool, head: bool, target: Option<&str>, count: Option<&i32>) -> Result<(), Error> {
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
+    /// Creates a migration from the difference between the database at head and the desired
+    /// schema in `schema_file`, or the configured schema file.
+    ///
+    /// The desired schema is applied to a scratch database and both are introspected; statements
+    /// that may lose data or hide a rename are preceded by a `-- REVIEW:` comment.
+    pub fn autogenerate_migration(&mut self, name: &str, schema_file: Option<&str>) -> Result<(), MigrateError> {
+        let head = self._get_head()?;
+        if self._get_current() != head {
+            println!("Database is not at head, upgrade before autogenerating");
+            return Ok(());
+        }
+
+        let path = schema_file.map(PathBuf::from).unwrap_or_else(|| self.schema_file.clone());
+        let desired_sql = placeholder::expand(&fs::read_to_string(&path)?, &self.vars)?;
+        let scratch = ScratchDatabase::create(&self.database_url)?;
+        let desired = {
+            let mut client = scratch.connect()?;
+            client.batch_execute(&desired_sql)?;
+            Schema::introspect(&mut client)?
+        };
+        let current = Schema::introspect(&mut self.client)?;
+
+        let diff = diff::diff(&current, &desired);
+        if diff.is_empty() {
+            println!("No schema changes detected");
+            return Ok(());
+        }
+        let stem = self._write_migration(name, &diff::render(&diff.up), &diff::render(&diff.down))?;
+        println!("Created migration: {}", stem);
+        if diff.review_count() > 0 {
+            println!("{} statement(s) marked for review", diff.review_count());
+        }
+        Ok(())
+    }
+
+    /// Writes the up and down files of a new migration on top of head and returns its file stem.
+    fn _write_migration(&mut self, name: &str, up_sql: &str, down_sql: &str) -> io::Result<String> {
+        let head = self._get_head()?;
+        let headers = [("Down Revision", head.unwrap_or_else(|| "None".to_string()))];
+        self._write_migration_files(name, &headers, up_sql, down_sql)
+    }
+
+    /// Writes the up and down files of a new migration with the given headers after its revision.
+    fn _write_migration_files(&mut self, name: &str, headers: &[(&str, String)], up_sql: &str, down_sql: &str) -> io::Result<String> {
+        write_new_migration(&self.migration_dir, &self.naming, name, headers, up_sql, down_sql)
+    }
+
+    /// Converts the migrations of another tool in `dir` into the migration directory, keeping their
+    /// versions as revisions, and marks the ones recorded in that tool's table as applied.
+    ///
+    /// Running it again skips the migrations that were already imported.
+    pub fn import_migrations(&mut self, source: Source, dir: &Path) -> Result<(), MigrateError> {
+        let Some(import) = import::convert(source, dir, &self.migration_dir)? else {
+            return Ok(());
+        };
+        self.mark_imported(source, &import.migrations)
+    }
+
+    /// Marks the imported migrations recorded in the other tool's table as applied and sets the
+    /// current revision to the last of them.
+    pub fn mark_imported(&mut self, source: Source, migrations: &[ImportedMigration]) -> Result<(), MigrateError> {
+        let applied = import::applied_revisions(source, &mut self.client, migrations)?;
+        let Some(current) = applied.last().cloned() else {
+            return Ok(());
+        };
+        if self.dry_run {
+            println!("Would mark {} migrations as applied, up to {}", applied.len(), current);
+            return Ok(());
+        }
+        let migrations = self._load_migrations()?;
+        for migration in migrations.iter().filter(|m| applied.contains(&m.revision)) {
+            if !self._is_applied(&migration.revision)? {
+                self._save_history(&migration.revision, &migration.name)?;
+                self._save_checksum(&migration.revision, &migration.checksum()?)?;
+            }
+        }
+        self._record_current(Some(current.clone()))?;
+        println!("Marked {} migrations as applied, current revision: {}", applied.len(), current);
+        Ok(())
+    }
+
+    /// Writes the migrations, parents first, in another tool's layout or as one script, with their
+    /// headers stripped and placeholders expanded. See `export::export`.
+    pub fn export_migrations(&mut self, format: Format, output: &Path) -> Result<(), MigrateError> {
+        export::export(&self.migration_dir, format, output, &self.vars)
+    }
+
+    /// Replaces the migrations from `from` to `to` with a single migration running their up SQL in
+    /// order and their down SQL in reverse, and points the migrations after `to` at it.
+    ///
+    /// The range must be a straight line of revisions. Databases that applied the whole range
+    /// adopt the squashed migration as applied on their next upgrade. See `squash::squash`.
+    pub fn squash_migrations(&mut self, from: &str, to: &str, name: Option<&str>) -> Result<(), MigrateError> {
+        let migration_dir = self.migration_dir.clone();
+        let naming = self.naming.clone();
+        squash::squash(&migration_dir, &naming, from, to, name, || self.current_revision())
+    }
+
+    pub fn run_migrations(&mut self, upgrade: bool, head: bool, target: Option<&str>, count: Option<&i32>) -> Result<(), MigrateError> {
+        let result = self._run_migrations(upgrade, head, target, count);
+        if let Err(error) = &result {
+            let context = HookContext {
+                point: HookPoint::OnError,
+                direction: self._direction(upgrade),
+                revision: self._get_current(),
+                error: Some(error.to_string()),
+            };
+            if let Err(hook_error) = self._run_hooks(&context) {
+                println!("on_error hook failed: {}", hook_error);
+            }
+        }
+        result
+    }
+
+    fn _run_migrations(&mut self, upgrade: bool, head: bool, target: Option<&str>, count: Option<&i32>) -> Result<(), MigrateError> {
+        let mut migrations = self._load_migrations()?;
+        self._sort_migrations(&mut migrations, upgrade);
+        self._verify_checksums(&migrations)?;
+        if !self.dry_run {
+            self._adopt_squashed(&migrations)?;
+        }
+
+        if upgrade && self.shadow_check && !self.dry_run {
+            self._validate_shadow()?;
+        }
+
+        let context = HookContext {
+            point: HookPoint::BeforeAll,
+            direction: self._direction(upgrade),
+            revision: self._get_current(),
+            error: None,
+        };
+        self._run_hooks(&context)?;
 
         if upgrade {
-            self._sort_paths(&mut paths, true);
-        } else {
-            self._sort_paths(&mut paths, false);
+            self._run_out_of_order(&migrations)?;
         }
 
         if head {
-            let _ = self._upgrade_head(&mut paths);
-        } else if !target.is_none() {
-            let _ = self._migrate_target(&mut paths, upgrade, target.unwrap());
-        } else if count != None {
-            let _ = self._migrate_count(&mut paths, upgrade, count.unwrap());
+            self._upgrade_head(&migrations)?;
+        } else if let Some(target) = target {
+            self._migrate_target(&migrations, upgrade, target)?;
+        } else if let Some(count) = count {
+            self._migrate_count(&migrations, upgrade, count)?;
+        }
+
+        if upgrade {
+            self._run_repeatable()?;
+        }
+
+        let context = HookContext {
+            point: HookPoint::AfterAll,
+            direction: self._direction(upgrade),
+            revision: self._get_current(),
+            error: None,
+        };
+        self._run_hooks(&context)?;
+
+        if upgrade && self.dump_schema && !self.dry_run {
+            self.dump_schema(None)?;
         }
         Ok(())
     }
 
-    pub fn get_head(&self) -> Result<(), Error> {
-        let migration_id = self._get_head();
-        if migration_id.is_none() {
-            println!("Head: {:?}", migration_id);
+    /// Runs `run_migrations` for every tenant schema, `concurrency` tenants at a time (default
+    /// `tenants.concurrency`, or 1), and prints a summary.
+    ///
+    /// Every tenant gets its own connection. Registered Rust hooks are not run for tenants.
+    pub fn run_tenant_migrations(&mut self, upgrade: bool, head: bool, target: Option<&str>, count: Option<&i32>, concurrency: Option<usize>) -> Result<(), MigrateError> {
+        let schemas = self.tenant_schemas()?;
+        if schemas.is_empty() {
+            println!("No tenant schemas found");
+            return Ok(());
+        }
+        let concurrency = concurrency.or(self.tenants.concurrency).unwrap_or(1);
+        let tenant_client = self._tenant_client();
+
+        let results = run_parallel(schemas, concurrency, |schema| {
+            let result = tenant_client.connect(schema, upgrade)
+                .and_then(|mut client| client.run_migrations(upgrade, head, target, count));
+            match &result {
+                Ok(()) => println!("Tenant {}: done", schema),
+                Err(error) => println!("Tenant {}: failed: {}", schema, error),
+            }
+            result
+        });
+
+        let total = results.len();
+        let failures: Vec<(String, String)> = results.into_iter()
+            .filter_map(|(schema, result)| result.err().map(|error| (schema, error.to_string())))
+            .collect();
+        if failures.is_empty() {
+            println!("Migrated {} tenants", total);
             return Ok(());
+        }
+        println!("{} of {} tenants failed:", failures.len(), total);
+        for (schema, error) in &failures {
+            println!("    {}: {}", schema, error);
+        }
+        Err(MigrateError::TenantFailures(failures))
+    }
+
+    /// Runs the shared seeds and the seeds of `env` that have not run yet or changed since they ran,
+    /// and returns their names; on a dry run the names of the seeds that would run.
+    pub fn run_seeds(&mut self, env: Option<&str>) -> Result<Vec<String>, MigrateError> {
+        let current = self._get_current();
+        let mut ran = Vec::new();
+        for seed in load_seeds(&self.seed_dir, env)? {
+            if let Some(requires) = &seed.requires {
+                if !self._is_dependency_applied(requires)? {
+                    println!("Skipping seed {}: requires revision {}", seed.name, requires);
+                    continue;
+                }
+            }
+
+            let sql = placeholder::expand(&seed.sql()?, &self.vars)?;
+            let checksum = sql_checksum(&sql);
+            let applied = self.client.query_opt(
+                "SELECT checksum FROM seeds WHERE name = $1",
+                &[&seed.name],
+            )?.map(|row| row.get::<_, String>("checksum"));
+            if applied.as_deref() == Some(checksum.as_str()) {
+                continue;
+            }
+
+            ran.push(seed.name.clone());
+            if self.dry_run {
+                println!("-- Seed {}\n{}", seed.name, sql);
+                continue;
+            }
+            self.client.batch_execute(&sql)?;
+            self.client.execute(
+                "INSERT INTO seeds (name, env, revision, checksum) VALUES ($1, $2, $3, $4) \
+                ON CONFLICT (name) DO UPDATE SET env = $2, revision = $3, checksum = $4, applied_at = CURRENT_TIMESTAMP",
+                &[&seed.name, &seed.env, &current, &checksum],
+            )?;
+            println!("Applied seed: {}", seed.name);
+        }
+        if ran.is_empty() {
+            println!("No seeds to run");
+        } else if self.dry_run {
+            println!("Would run {} seed(s)", ran.len());
+        }
+        Ok(ran)
+    }
+
+    /// Downgrades every applied migration, forgets which seeds ran and upgrades back to head.
+    pub fn reset(&mut self) -> Result<(), MigrateError> {
+        let migrations = self._load_migrations()?;
+        let applied = self._applied_count(&migrations);
+        if applied > 0 {
+            self.run_migrations(false, false, None, Some(&(applied as i32)))?;
+        }
+        if !self.dry_run {
+            self.client.execute("DELETE FROM seeds", &[])?;
+        }
+        self.run_migrations(true, true, None, None)
+    }
+
+    /// Writes the normalized schema of the database to `path`, or to the configured schema file,
+    /// and returns what was written.
+    pub fn dump_schema(&mut self, path: Option<&str>) -> Result<String, MigrateError> {
+        let path = path.map(PathBuf::from).unwrap_or_else(|| self.schema_file.clone());
+        let schema = Schema::introspect(&mut self.client)?;
+        let current = self._get_current();
+
+        let dump = format!(
+            "-- Schema generated by pg_migrate, do not edit\n-- Revision: {}\n\n{}",
+            current.as_deref().unwrap_or("None"),
+            schema.to_sql()
+        );
+        fs::write(&path, &dump)?;
+
+        println!("Schema written to {}", path.display());
+        Ok(dump)
+    }
+
+    /// Checks on a scratch database that every down migration undoes its up migration.
+    ///
+    /// Each migration is applied up, then down, and the schema compared with the one before the up;
+    /// it is then applied up again for the next migration. Returns whether every migration passed.
+    pub fn check_roundtrip(&mut self) -> Result<bool, MigrateError> {
+        let scratch = ScratchDatabase::create(&self.database_url)?;
+        let mut scratch_client = self._scratch_client(scratch.url())?;
+        let mut migrations = self._load_migrations()?;
+        self._sort_migrations(&mut migrations, true);
+
+        let mut failed = 0;
+        for migration in &migrations {
+            let before = Schema::introspect(&mut scratch_client.client)?;
+            scratch_client._apply_up(migration)?;
+            let after_up = Schema::introspect(&mut scratch_client.client)?;
+            scratch_client._apply_down(migration)?;
+            let mut differences = diff::describe(&before, &Schema::introspect(&mut scratch_client.client)?);
+
+            let reapplied = scratch_client._apply_up(migration);
+            match &reapplied {
+                Ok(()) => differences.extend(
+                    diff::describe(&after_up, &Schema::introspect(&mut scratch_client.client)?)
+                        .into_iter()
+                        .map(|difference| format!("{} after re-applying up", difference)),
+                ),
+                Err(error) => differences.push(format!("re-applying up failed: {}", error)),
+            }
+
+            if differences.is_empty() {
+                println!("{} {}: ok", migration.revision, migration.name);
+            } else {
+                failed += 1;
+                println!("{} {}: down does not undo up", migration.revision, migration.name);
+                for difference in differences {
+                    println!("    {}", difference);
+                }
+            }
+            if reapplied.is_err() {
+                println!("Stopping, later migrations build on this one");
+                break;
+            }
+        }
+
+        if failed == 0 {
+            println!("All {} migrations round-trip", migrations.len());
         } else {
-            println!("Head: {}", migration_id.unwrap());
-            Ok(())
+            println!("{} of {} migrations failed the round-trip check", failed, migrations.len());
+        }
+        Ok(failed == 0)
+    }
+
+    /// Replays the migrations on a shadow database and compares its schema with this database.
+    ///
+    /// Migrations up to the current revision must reproduce the current schema, which catches
+    /// changes made outside of migrations; the pending ones must then apply cleanly. Returns
+    /// whether the validation passed.
+    pub fn validate_shadow(&mut self) -> Result<bool, MigrateError> {
+        match self._validate_shadow() {
+            Ok(()) => {
+                println!("Shadow database matches");
+                Ok(true)
+            }
+            Err(MigrateError::ShadowMismatch(differences)) => {
+                println!("Shadow database does not match:");
+                for difference in differences {
+                    println!("    {}", difference);
+                }
+                Ok(false)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn _validate_shadow(&mut self) -> Result<(), MigrateError> {
+        let mut scratch = None;
+        let url = match self.shadow_url.clone() {
+            Some(url) => {
+                DbClient::_reset_database(&url)?;
+                url
+            }
+            None => scratch.insert(ScratchDatabase::create(&self.database_url)?).url().to_string(),
+        };
+        let mut shadow = self._scratch_client(&url)?;
+        let mut migrations = self._load_migrations()?;
+        self._sort_migrations(&mut migrations, true);
+
+        let current = self._get_current();
+        if let Some(current) = &current {
+            if !migrations.iter().any(|migration| &migration.revision == current) {
+                return Err(MigrateError::ShadowMismatch(vec![format!("current revision {} has no migration", current)]));
+            }
         }
+        let applied = self._applied_count(&migrations);
+        for migration in &migrations[..applied] {
+            shadow._apply_up(migration)?;
+        }
+
+        let differences = diff::describe(&Schema::introspect(&mut shadow.client)?, &Schema::introspect(&mut self.client)?);
+        if !differences.is_empty() {
+            return Err(MigrateError::ShadowMismatch(differences));
+        }
+
+        for migration in &migrations[applied..] {
+            shadow._apply_up(migration)?;
+        }
+        Ok(())
+    }
+
+    /// Drops every user schema of the database at `url`, leaving an empty `public` schema.
+    pub(crate) fn _reset_database(url: &str) -> Result<(), MigrateError> {
+        let mut client = Client::connect(url, NoTls)?;
+        let schema = Schema::introspect(&mut client)?;
+        for name in &schema.schemas {
+            client.batch_execute(&format!("DROP SCHEMA {} CASCADE", quote_ident(name)))?;
+        }
+        client.batch_execute("DROP SCHEMA IF EXISTS public CASCADE; CREATE SCHEMA public;")?;
+        Ok(())
+    }
+
+    pub fn get_head(&self) -> Result<(), MigrateError> {
+        match self._get_head()? {
+            Some(migration_id) => println!("Head: {}", migration_id),
+            None => println!("Head: {:?}", None::<String>),
+        }
+        Ok(())
+    }
+
+    /// The current revision of the selected module, `None` at base.
+    pub fn current_revision(&mut self) -> Result<Option<String>, MigrateError> {
+        let row = self.client.query_opt(
+            "SELECT migration_id FROM migrations WHERE module = $1 ORDER BY id DESC LIMIT 1",
+            &[&self.module],
+        )?;
+        Ok(row.and_then(|row| row.get("migration_id")))
     }
 
     pub fn get_current(&mut self) {
-        let migration_id = self._get_current();
-        if migration_id.is_none() {
-            println!("Current: {:?}", migration_id);
-        } else {
-            println!("Current: {}", migration_id.unwrap());
+        match self._get_current() {
+            Some(migration_id) => println!("Current: {}", migration_id),
+            None => println!("Current: {:?}", None::<String>),
         }
     }
 
     pub fn get_history(&mut self) -> io::Result<()> {
         let result = self.client.query(
-            "SELECT * FROM history ORDER BY ID",
-            &[]
+            "SELECT * FROM history WHERE module = $1 ORDER BY ID",
+            &[&self.module]
         ).expect("Failed to get history");
         let rows = result.iter();
 
@@ -137,97 +745,219 @@ impl DbClient {
         Ok(())
     }
 
-    fn _upgrade_head(&mut self, paths: &mut Vec<PathBuf>) -> Result<(), Error> {
-        let suffix = { "_up.sql" };
-        let current = self._get_current();
-        let head = self._get_head().unwrap();
-        let mut running = false;
-        for path in paths {
-            if path.to_str().unwrap().ends_with(suffix) {
-                if current.is_none() {
-                    running = true;
-                }
-                if !current.is_none() && path.to_str().unwrap().contains(current.as_deref().unwrap()) {
-                    running = true;
-                } else if running {
-                    let migration = fs::read_to_string(&path).unwrap();
-                    self.client.batch_execute(&migration)?;
-
-                    let (_, _, migration_id, migration_name) = self._get_migration_details(&path);
-
-                    self._record_current(Some(migration_id.clone()))?;
-                    self._save_history(&migration_id, &migration_name)?;
-                    if path.to_str().unwrap().contains(&head) {
-                        println!("Upgraded to head: {}", &head);
-                        break;
-                    }
+    /// Prints a migration, found by revision, unique revision prefix, name or reference: its parents and
+    /// children, files with their checksums, whether and when it was applied, and its SQL,
+    /// highlighted when printing to a terminal.
+    pub fn show_migration(&mut self, reference: &str) -> Result<(), MigrateError> {
+        let mut migrations = self._load_migrations()?;
+        self._sort_migrations(&mut migrations, true);
+        let revision = self._resolve_target(&migrations, reference)?
+            .ok_or_else(|| MigrateError::UnknownRevision(reference.to_string()))?;
+        let migration = migrations.iter().find(|m| m.revision == revision).unwrap();
+        let children: Vec<&str> = migrations.iter()
+            .filter(|m| m.down_revisions.contains(&migration.revision))
+            .map(|m| m.revision.as_str())
+            .collect();
+        let list = |revisions: Vec<&str>| if revisions.is_empty() { "None".to_string() } else { revisions.join(", ") };
+
+        println!("Revision:    {}", migration.revision);
+        println!("Name:        {}", migration.name);
+        if let Some(description) = &migration.description {
+            println!("Description: {}", description);
+        }
+        println!("Parents:     {}", list(migration.down_revisions.iter().map(|s| s.as_str()).collect()));
+        println!("Children:    {}", list(children));
+        if !migration.depends_on.is_empty() {
+            println!("Depends on:  {}", migration.depends_on.join(", "));
+        }
+        if !migration.replaces.is_empty() {
+            println!("Replaces:    {}", migration.replaces.join(", "));
+        }
+        if migration.is_directory() {
+            println!("Directory:   {}", migration.path.display());
+        }
+        println!("Files:");
+        for file in migration.up_files.iter().chain(migration.down_files.iter()) {
+            println!("    {}  {}", sql_checksum(&fs::read_to_string(file)?), file.display());
+        }
+        let checksum = migration.checksum()?;
+        println!("Checksum:    {}", checksum);
+
+        let row = self.client.query_opt(
+            "SELECT created_at, checksum FROM history WHERE module = $1 AND migration_id = $2",
+            &[&self.module, &migration.revision],
+        )?;
+        let status = match row {
+            Some(row) => {
+                let applied_at: Option<chrono::NaiveDateTime> = row.get("created_at");
+                let recorded: Option<String> = row.get("checksum");
+                let changed = if recorded.is_some_and(|recorded| recorded != checksum) { ", changed since" } else { "" };
+                match applied_at {
+                    Some(applied_at) => format!("applied at {}{}", applied_at.format("%Y-%m-%d %H:%M:%S"), changed),
+                    None => format!("applied{}", changed),
                 }
             }
+            None => "pending".to_string(),
+        };
+        let current = if self._get_current().as_deref() == Some(migration.revision.as_str()) { " (current)" } else { "" };
+        println!("Status:      {}{}", status, current);
+
+        let terminal = io::stdout().is_terminal();
+        for (direction, sql) in [("Up", migration.up_sql()?), ("Down", migration.down_sql()?)] {
+            let sql = strip_headers(&sql);
+            println!("\n{}:\n{}", direction, if terminal { highlight::highlight_sql(sql) } else { sql.to_string() });
+        }
+        Ok(())
+    }
+
+    /// Prints the current revision of every tenant schema and how many migrations it has pending.
+    pub fn get_status_all_tenants(&mut self) -> Result<(), MigrateError> {
+        let mut migrations = self._load_migrations()?;
+        self._sort_migrations(&mut migrations, true);
+
+        println!("        Tenant        |             Migration ID            |  Status");
+        println!("---------------------------------------------------------------------------");
+        for schema in self.tenant_schemas()? {
+            // Reads the tenant's tables in place rather than through `set_tenant`, which would create them.
+            let table = format!("{}.migrations", quote_ident(&schema));
+            let initialised: bool = self.client.query_one("SELECT to_regclass($1) IS NOT NULL", &[&table])?.get(0);
+            if !initialised {
+                println!("{} | None | uninitialised", schema);
+                continue;
+            }
+            let current: Option<String> = self.client.query_opt(
+                &format!("SELECT migration_id FROM {} WHERE module = $1 ORDER BY id DESC LIMIT 1", table),
+                &[&self.module],
+            )?.and_then(|row| row.get(0));
+            let applied = match &current {
+                Some(current) => migrations.iter().position(|m| &m.revision == current).map_or(0, |i| i + 1),
+                None => 0,
+            };
+            let pending = migrations.len() - applied;
+            let status = if pending == 0 { "up to date".to_string() } else { format!("{} pending", pending) };
+            println!("{} | {} | {}", schema, current.as_deref().unwrap_or("None"), status);
         }
-        if !running {
+        Ok(())
+    }
+
+    /// Prints the status of every module, see `get_status`.
+    pub fn get_status_all(&mut self) -> Result<(), MigrateError> {
+        let module = self.module.clone();
+        for name in self.modules() {
+            self.set_module(&name)?;
+            println!("Module: {}", name);
+            let status = self.get_status();
+            println!();
+            if status.is_err() {
+                self.set_module(&module)?;
+                return status;
+            }
+        }
+        self.set_module(&module)
+    }
+
+    /// Prints every migration with whether it is applied, pending or pending out of order.
+    pub fn get_status(&mut self) -> Result<(), MigrateError> {
+        let mut migrations = self._load_migrations()?;
+        self._sort_migrations(&mut migrations, true);
+        let out_of_order: Vec<String> = self._out_of_order(&migrations)?.iter().map(|m| m.revision.clone()).collect();
+
+        println!("             Migration ID            |  Name  |  Status");
+        println!("----------------------------------------------------------");
+        for migration in &migrations {
+            let status = if out_of_order.contains(&migration.revision) {
+                "pending (out of order)"
+            } else if self._is_applied(&migration.revision)? {
+                "applied"
+            } else if self._is_squash_adoptable(migration)? {
+                "applied (squashed)"
+            } else {
+                "pending"
+            };
+            println!("{} | {} | {}", migration.revision, migration.name, status);
+        }
+        Ok(())
+    }
+
+    fn _upgrade_head(&mut self, migrations: &[Migration]) -> Result<(), MigrateError> {
+        let current = self._get_current();
+        let head = self._get_head()?;
+        let mut running = current.is_none();
+        let mut ran = false;
+        for migration in migrations {
+            if !running {
+                running = current.as_deref() == Some(migration.revision.as_str());
+                continue;
+            }
+            if self._is_applied(&migration.revision)? {
+                continue;
+            }
+            self._apply_up(migration)?;
+            ran = true;
+        }
+        if ran {
+            println!("Upgraded to head: {}", head.unwrap_or_default());
+        } else {
             println!("No migrations to run");
         }
 
         Ok(())
     }
 
-    fn _migrate_target(&mut self, paths: &mut Vec<PathBuf>, upgrade: bool, target: &str) -> Result<(), Error> {
-        let suffix = if upgrade { "_up.sql" } else { "_down.sql" };
+    fn _migrate_target(&mut self, migrations: &[Migration], upgrade: bool, target: &str) -> Result<(), MigrateError> {
         let direction = if upgrade { "Upgraded" } else { "Downgraded" };
         let current = self._get_current();
 
-        let target_exists: bool = self._if_target_exists(target);
-        if !target_exists {
-            println!("Target migration does not exist");
+        let resolved = self._resolve_target(migrations, target)?;
+        let is_target = |migration: &Migration| resolved.as_deref() == Some(migration.revision.as_str());
+
+        // Upgrading moves to a target after the current revision, downgrading to one at or before it.
+        let remaining: Vec<&Migration> = match &current {
+            Some(current) => migrations.iter().skip_while(|m| &m.revision != current).skip(usize::from(upgrade)).collect(),
+            None if upgrade => migrations.iter().collect(),
+            None => Vec::new(),
+        };
+        let reachable = match &resolved {
+            Some(_) => remaining.iter().any(|m| is_target(m)),
+            None => !upgrade && current.is_some(),
+        };
+        if !reachable {
+            println!("No migrations to run");
             return Ok(());
         }
 
-        let mut running = false;
-        for path in paths {
-            if path.to_str().unwrap().ends_with(suffix) {
-                if current.is_none() || !upgrade && path.to_str().unwrap().contains(current.as_deref().unwrap()) {
-                    running = true;
+        let mut running = upgrade && current.is_none();
+        for migration in migrations {
+            if !running {
+                running = current.as_deref() == Some(migration.revision.as_str());
+                if !running || upgrade {
+                    continue;
+                }
+            }
+            if upgrade {
+                if !self._is_applied(&migration.revision)? {
+                    self._apply_up(migration)?;
+                }
+                if is_target(migration) {
+                    println!("{} to target: {}", direction, target);
+                    return Ok(());
                 }
-                if !current.is_none() && path.to_str().unwrap().contains(current.as_deref().unwrap()) && upgrade {
-                    running = true;
-                } else if running {
-                    if upgrade {
-                        let migration = fs::read_to_string(&path).unwrap();
-                        self.client.batch_execute(&migration)?;
-                        let (_, _, migration_id, migration_name) = self._get_migration_details(&path);
-
-                        self._record_current(Some(migration_id.clone()))?;
-                        self._save_history(&migration_id.clone(), &migration_name)?;
-
-                        if path.to_str().unwrap().contains(target) {
-                            println!("{} to target: {}", direction, target);
-                            break;
-                        }
-                    } else {
-                        let down_migration_id: Option<String> = self._get_down_migration_id(path.to_str().unwrap());
-                        if path.to_str().unwrap().contains(target) {
-                            println!("{} to target: {}", direction, &target);
-                            break;
-                        }
-
-                        let migration = fs::read_to_string(&path).unwrap();
-                        self.client.batch_execute(&migration)?;
-                        let (_, _, migration_id, _) = self._get_migration_details(&path);
-
-                        let _ = self._record_current(down_migration_id.clone());
-                        self._remove_from_history(&migration_id.clone())?;
-                    }
+            } else {
+                if is_target(migration) {
+                    println!("{} to target: {}", direction, target);
+                    return Ok(());
+                }
+                if self._is_applied(&migration.revision)? {
+                    self._apply_down(migration)?;
                 }
             }
         }
-        if !running {
-            println!("No migrations to run");
-        }
+        // Only a downgrade to base runs past the last migration.
+        println!("{} to target: {}", direction, target);
         Ok(())
     }
 
-    fn _migrate_count(&mut self, paths: &mut Vec<PathBuf>, upgrade: bool, count: &i32) -> Result<(), Error> {
-        let suffix = if upgrade { "_up.sql" } else { "_down.sql" };
+    fn _migrate_count(&mut self, migrations: &[Migration], upgrade: bool, count: &i32) -> Result<(), MigrateError> {
         let direction = if upgrade { "Upgraded" } else { "Downgraded" };
         let current = self._get_current();
 
@@ -236,83 +966,289 @@ impl DbClient {
             return Ok(());
         }
 
-        let mut running = false;
+        let mut running = upgrade && current.is_none();
         let mut counter = 0;
-        for path in paths.iter() {
-            if path.to_str().unwrap().ends_with(suffix) {
-                if current.is_none() || !upgrade && path.to_str().unwrap().contains(current.as_deref().unwrap()) {
-                    running = true;
+        for migration in migrations {
+            if !running {
+                running = current.as_deref() == Some(migration.revision.as_str());
+                if !running || upgrade {
+                    continue;
                 }
-                if !current.is_none() && path.to_str().unwrap().contains(current.as_deref().unwrap()) && upgrade {
-                    running = true;
-                } else if running && counter < *count {
-                    let sql = fs::read_to_string(&path).unwrap();
-                    let _ = self.client.execute(&sql, &[]);
-                    let (_, _, migration_id, migration_name) = self._get_migration_details(&path);
-                    let down_migration_id: Option<String> = self._get_down_migration_id(path.to_str().unwrap());
-
-                    if upgrade {
-                        self._record_current(Some(migration_id.clone()))?;
-                        self._save_history(&migration_id.clone(), &migration_name)?;
-                    } else {
-                        match down_migration_id.as_deref() {
-                            Some("None") => {
-                                let _ = self._record_current(None);
-                            }
-                            Some(_string) => {
-                                let _ = self._record_current(down_migration_id.clone());
-                                self._remove_from_history(&migration_id.clone())?;
-                            }
-                            None => {
-                                let _ = self._record_current(None);
-                            }
-                        }
-                    }
-                    counter += 1;
-                    if counter == *count {
-                        if upgrade {
-                            println!("{} to: {} {}", direction, &migration_id, &migration_name);
-                        } else {
-                            if !down_migration_id.is_none() {
-                                println!("{} to: {:?}", direction, &down_migration_id.unwrap());
-                            } else {
-                                println!("{} to: {:?}", direction, &down_migration_id);
-                            }
-                        }
-
-                        break;
-                    }
+            }
+            // Migrations applied out of order are already applied above the current revision,
+            // and were never applied below it.
+            if upgrade == self._is_applied(&migration.revision)? {
+                continue;
+            }
+            if upgrade {
+                self._apply_up(migration)?;
+            } else {
+                self._apply_down(migration)?;
+            }
+            counter += 1;
+            if counter == *count {
+                if upgrade {
+                    println!("{} to: {} {}", direction, &migration.revision, &migration.name);
+                } else {
+                    println!("{} to: {:?}", direction, migration.down_revision().unwrap_or("None"));
                 }
+                break;
             }
         }
-        if !running {
-            println!("No migrations to run");
+
+        Ok(())
+    }
+
+    /// Pending migrations that sort before the current revision, e.g. a hotfix merged after a
+    /// later migration was applied. `migrations` must be in ascending order.
+    fn _out_of_order<'a>(&mut self, migrations: &'a [Migration]) -> Result<Vec<&'a Migration>, MigrateError> {
+        let applied = self._applied_count(migrations);
+        let mut pending = Vec::new();
+        for migration in &migrations[..applied.saturating_sub(1)] {
+            if !self._is_applied(&migration.revision)? && !self._is_squash_adoptable(migration)? {
+                pending.push(migration);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Applies the out-of-order migrations when allowed, and refuses to go on otherwise.
+    fn _run_out_of_order(&mut self, migrations: &[Migration]) -> Result<(), MigrateError> {
+        let pending = self._out_of_order(migrations)?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if !self.allow_out_of_order {
+            println!("Found pending migrations older than the current revision:");
+            for migration in &pending {
+                println!("    {} {}", migration.revision, migration.name);
+            }
+            println!("Run with --allow-out-of-order to apply them");
+            return Err(MigrateError::OutOfOrder(pending.iter().map(|m| m.revision.clone()).collect()));
         }
 
+        let current = self._get_current();
+        for migration in pending {
+            self._apply_up(migration)?;
+            println!("Applied out of order: {} {}", migration.revision, migration.name);
+        }
+        if !self.dry_run {
+            self._record_current(current)?;
+        }
         Ok(())
     }
 
-    fn _get_head(&self) -> Option<String> {
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
+    /// Whether `migration` squashes migrations that were all applied, so the next upgrade marks it
+    /// as applied without running it.
+    fn _is_squash_adoptable(&mut self, migration: &Migration) -> Result<bool, Error> {
+        if migration.replaces.is_empty() {
+            return Ok(false);
+        }
+        for revision in &migration.replaces {
+            if !self._is_applied(revision)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 
-        self._sort_paths(&mut paths, false);
+    /// Marks squashed migrations as applied where every migration they replace was applied, and
+    /// moves the current revision and recorded dependencies over to them.
+    fn _adopt_squashed(&mut self, migrations: &[Migration]) -> Result<(), MigrateError> {
+        for migration in migrations.iter().filter(|m| !m.replaces.is_empty()) {
+            if self._is_applied(&migration.revision)? {
+                continue;
+            }
+            let mut pending = Vec::new();
+            for revision in &migration.replaces {
+                if !self._is_applied(revision)? {
+                    pending.push(revision.clone());
+                }
+            }
+            if pending.len() == migration.replaces.len() {
+                continue;
+            }
+            if !pending.is_empty() {
+                return Err(MigrateError::PartiallySquashed { revision: migration.revision.clone(), pending });
+            }
+
+            let current = self._get_current();
+            self.client.execute(
+                "DELETE FROM history WHERE module = $1 AND migration_id = ANY($2)",
+                &[&self.module, &migration.replaces],
+            )?;
+            for revision in &migration.replaces {
+                self.client.execute(
+                    "UPDATE history SET depends_on = array_replace(depends_on, $1, $2) WHERE $1 = ANY(depends_on)",
+                    &[&self._qualify(revision), &self._qualify(&migration.revision)],
+                )?;
+            }
+            self._save_history(&migration.revision, &migration.name)?;
+            self._save_checksum(&migration.revision, &migration.checksum()?)?;
+            self._save_dependencies(&migration.revision, &migration.depends_on)?;
+            if current.is_some_and(|current| migration.replaces.contains(&current)) {
+                self._record_current(Some(migration.revision.clone()))?;
+            }
+            println!("Marked squashed migration {} as applied", migration.revision);
+        }
+        Ok(())
+    }
 
-        if paths.len() > 0 {
-            let filename = &paths[0];
-            let split: Vec<&str> = filename.to_str().expect("REASON").split('_').collect();
-            let migration_id = split[2];
-            Some(String::from(migration_id))
+    fn _apply_up(&mut self, migration: &Migration) -> Result<(), MigrateError> {
+        if !self.dry_run {
+            for dependency in &migration.depends_on {
+                if !self._is_dependency_applied(dependency)? {
+                    return Err(MigrateError::MissingDependency {
+                        revision: migration.revision.clone(),
+                        dependency: dependency.clone(),
+                    });
+                }
+            }
+        }
+        let sql = placeholder::expand(&migration.up_sql()?, &self.vars)?;
+        self._run_each_hooks(HookPoint::BeforeEach, Direction::Upgrade, migration)?;
+        if self.dry_run {
+            println!("-- Upgrade {} {}\n{}", migration.revision, migration.name, sql);
         } else {
-            None
+            self.client.batch_execute(&sql)?;
+            self._record_current(Some(migration.revision.clone()))?;
+            self._save_history(&migration.revision, &migration.name)?;
+            self._save_checksum(&migration.revision, &migration.checksum()?)?;
+            self._save_dependencies(&migration.revision, &migration.depends_on)?;
         }
+        self._run_each_hooks(HookPoint::AfterEach, Direction::Upgrade, migration)
+    }
+
+    fn _apply_down(&mut self, migration: &Migration) -> Result<(), MigrateError> {
+        if !self.dry_run {
+            let dependents = self._get_dependents(&migration.revision)?;
+            if !dependents.is_empty() {
+                return Err(MigrateError::RequiredBy { revision: migration.revision.clone(), dependents });
+            }
+        }
+        let sql = placeholder::expand(&migration.down_sql()?, &self.vars)?;
+        self._run_each_hooks(HookPoint::BeforeEach, Direction::Downgrade, migration)?;
+        if self.dry_run {
+            println!("-- Downgrade {} {}\n{}", migration.revision, migration.name, sql);
+        } else {
+            self.client.batch_execute(&sql)?;
+            self._record_current(migration.down_revision().map(|s| s.to_string()))?;
+            self._remove_from_history(&migration.revision)?;
+        }
+        self._run_each_hooks(HookPoint::AfterEach, Direction::Downgrade, migration)
+    }
+
+    fn _run_each_hooks(&mut self, point: HookPoint, direction: Direction, migration: &Migration) -> Result<(), MigrateError> {
+        let context = HookContext {
+            point,
+            direction,
+            revision: Some(migration.revision.clone()),
+            error: None,
+        };
+        self._run_hooks(&context)
+    }
+
+    /// Runs the SQL hooks for `context.point`, then the registered Rust hooks.
+    ///
+    /// SQL hooks can use the `${hook}`, `${direction}`, `${revision}` and `${error}` placeholders.
+    /// Rust hooks are skipped in dry-run mode.
+    fn _run_hooks(&mut self, context: &HookContext) -> Result<(), MigrateError> {
+        let mut vars = self.vars.clone();
+        vars.insert("hook".to_string(), context.point.name().to_string());
+        vars.insert("direction".to_string(), context.direction.to_string());
+        vars.insert("revision".to_string(), context.revision.clone().unwrap_or_else(|| "None".to_string()));
+        if let Some(error) = &context.error {
+            vars.insert("error".to_string(), error.clone());
+        }
+
+        for path in load_sql_hooks(&self.migration_dir, context.point)? {
+            let sql = placeholder::expand(&fs::read_to_string(&path)?, &vars)?;
+            if self.dry_run {
+                println!("-- Hook {}\n{}", path.display(), sql);
+            } else {
+                self.client.batch_execute(&sql)?;
+            }
+        }
+
+        if !self.dry_run {
+            for (point, hook) in self.hooks.iter_mut() {
+                if *point == context.point {
+                    hook(&mut self.client, context)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn _direction(&self, upgrade: bool) -> Direction {
+        if upgrade { Direction::Upgrade } else { Direction::Downgrade }
+    }
+
+    /// Applies the repeatable migrations that are new or changed since they last ran.
+    fn _run_repeatable(&mut self) -> Result<(), MigrateError> {
+        for mut repeatable in load_repeatable_migrations(&self.migration_dir)? {
+            if self.module != DEFAULT_MODULE {
+                repeatable.name = format!("{}/{}", self.module, repeatable.name);
+            }
+            let sql = placeholder::expand(&repeatable.sql()?, &self.vars)?;
+            let checksum = sql_checksum(&sql);
+            let applied = self.client.query_opt(
+                "SELECT checksum FROM repeatable_migrations WHERE name = $1",
+                &[&repeatable.name],
+            )?.map(|row| row.get::<_, String>("checksum"));
+            if applied.as_deref() == Some(checksum.as_str()) {
+                continue;
+            }
+
+            if self.dry_run {
+                println!("-- Repeatable {}\n{}", repeatable.name, sql);
+                continue;
+            }
+            self.client.batch_execute(&sql)?;
+            self.client.execute(
+                "INSERT INTO repeatable_migrations (name, checksum) VALUES ($1, $2) \
+                ON CONFLICT (name) DO UPDATE SET checksum = $2, applied_at = CURRENT_TIMESTAMP",
+                &[&repeatable.name, &checksum],
+            )?;
+            println!("Applied repeatable migration: {}", repeatable.name);
+        }
+        Ok(())
+    }
+
+    fn _tenant_client(&self) -> TenantClient {
+        TenantClient {
+            database_url: self.database_url.clone(),
+            migration_dir: self.migration_dir.clone(),
+            module: self.module.clone(),
+            vars: self.vars.clone(),
+            dry_run: self.dry_run,
+            allow_out_of_order: self.allow_out_of_order,
+        }
+    }
+
+    /// A client for the database at `url` that runs the same migrations with the same placeholders.
+    fn _scratch_client(&self, url: &str) -> Result<DbClient, MigrateError> {
+        let mut client = DbClient::new(url)?;
+        client.migration_dir = self.migration_dir.clone();
+        client.module = self.module.clone();
+        client.vars = self.vars.clone();
+        client.dump_schema = false;
+        client.shadow_check = false;
+        Ok(client)
+    }
+
+    fn _load_migrations(&self) -> io::Result<Vec<Migration>> {
+        load_migrations(&self.migration_dir)
+    }
+
+    fn _get_head(&self) -> io::Result<Option<String>> {
+        Ok(self._load_migrations()?.last().map(|migration| migration.revision.clone()))
     }
 
     fn _get_current(&mut self) -> Option<String> {
         match self.client.query_opt(
-            "SELECT migration_id FROM migrations ORDER BY id DESC LIMIT 1",
-            &[]
+            "SELECT migration_id FROM migrations WHERE module = $1 ORDER BY id DESC LIMIT 1",
+            &[&self.module]
         ) {
             Ok(Some(row)) => {
                 let migration_id: Option<&str> = row.get("migration_id");
@@ -325,124 +1261,154 @@ impl DbClient {
 
     fn _record_current(&mut self, migration_id: Option<String>) -> Result<(), Error> {
         self.client.execute(
-            "DELETE FROM migrations",
-            &[]
+            "DELETE FROM migrations WHERE module = $1",
+            &[&self.module]
         )?;
 
         self.client.execute(
-            "INSERT INTO migrations (migration_id) VALUES ($1)",
-            &[&migration_id],
+            "INSERT INTO migrations (migration_id, module) VALUES ($1, $2)",
+            &[&migration_id, &self.module],
         )?;
         Ok(())
     }
 
     fn _save_history(&mut self, migration_id: &str, migration_name: &str) -> Result<(), Error> {
         self.client.execute(
-            "INSERT INTO history (migration_id, name) VALUES ($1, $2) \
-            ON CONFLICT (migration_id) DO NOTHING",
-            &[&migration_id, &migration_name],
+            "INSERT INTO history (migration_id, name, module) VALUES ($1, $2, $3) \
+            ON CONFLICT (module, migration_id) DO NOTHING",
+            &[&migration_id, &migration_name, &self.module],
         )?;
         Ok(())
     }
 
-    fn _remove_from_history(&mut self, migration_id: &str) -> Result<(), Error> {
+    fn _save_checksum(&mut self, migration_id: &str, checksum: &str) -> Result<(), Error> {
         self.client.execute(
-            "DELETE FROM history WHERE migration_id = $1",
-            &[&migration_id],
+            "UPDATE history SET checksum = $2 WHERE module = $3 AND migration_id = $1",
+            &[&migration_id, &checksum, &self.module],
         )?;
         Ok(())
     }
 
-    fn _sort_paths(&self, paths: &mut Vec<PathBuf>, asc: bool) -> () {
-        paths.sort_by(|a, b| {
-            let a_metadata = fs::metadata(a).unwrap();
-            let b_metadata = fs::metadata(b).unwrap();
-            let a_time = a_metadata.created().unwrap();
-            let b_time = b_metadata.created().unwrap();
-            if asc {
-                return a_time.cmp(&b_time);
-            }
-            b_time.cmp(&a_time)
-        });
+    /// Saves the dependencies as `module:revision`, so dependents are found from any module.
+    fn _save_dependencies(&mut self, migration_id: &str, depends_on: &[String]) -> Result<(), Error> {
+        let depends_on: Vec<String> = depends_on.iter().map(|dependency| self._qualify(dependency)).collect();
+        self.client.execute(
+            "UPDATE history SET depends_on = $2 WHERE module = $3 AND migration_id = $1",
+            &[&migration_id, &depends_on, &self.module],
+        )?;
+        Ok(())
     }
 
-    fn _if_target_exists(&self, target: &str) -> bool {
-        let migrations_dir = "migrations";
-        let paths: Vec<PathBuf> = fs::read_dir(migrations_dir).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
+    /// Applied migrations that declared `migration_id` in their `-- Depends On` header. Those of
+    /// other modules are returned as `module:revision`.
+    fn _get_dependents(&mut self, migration_id: &str) -> Result<Vec<String>, Error> {
+        let rows = self.client.query(
+            "SELECT module, migration_id FROM history WHERE $1 = ANY(depends_on) ORDER BY id",
+            &[&self._qualify(migration_id)],
+        )?;
+        Ok(rows.iter().map(|row| {
+            let module: String = row.get("module");
+            let migration_id: String = row.get("migration_id");
+            if module == self.module { migration_id } else { format!("{}:{}", module, migration_id) }
+        }).collect())
+    }
 
-        for path in paths {
-            if path.to_str().unwrap().contains(target) {
-                return true;
-            }
+    /// `module:revision` for a `-- Depends On` entry; entries without a module are in the selected one.
+    fn _qualify(&self, revision: &str) -> String {
+        match revision.split_once(':') {
+            Some(_) => revision.to_string(),
+            None => format!("{}:{}", self.module, revision),
         }
-        false
     }
 
-    fn _if_count_valid(&mut self, count: &i32, upgrade: bool) -> bool {
-        let migrations_dir = "migrations";
-        let mut paths: Vec<PathBuf> = fs::read_dir(migrations_dir).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-        let suffix = if upgrade { "_up.sql" } else { "_down.sql" };
-        self._sort_paths(&mut paths, upgrade);
-        let current = self._get_current();
-        let mut running = false;
-        let mut counter = 0;
-        for path in paths.iter() {
-            if path.to_str().unwrap().ends_with(suffix) {
-                if upgrade && current.is_none() {
-                    running = true;
-                }
-                if !current.is_none() && path.to_str().unwrap().contains(current.as_deref().unwrap()) {
-                    running = true;
-                    if !upgrade {
-                        counter += 1;
-                        if counter == *count {
-                            return true;
-                        }
-                    }
-                } else if running && counter < *count {
-                    counter += 1;
-                    if counter == *count {
-                        return true;
-                    }
+    fn _remove_from_history(&mut self, migration_id: &str) -> Result<(), Error> {
+        self.client.execute(
+            "DELETE FROM history WHERE module = $1 AND migration_id = $2",
+            &[&self.module, &migration_id],
+        )?;
+        Ok(())
+    }
+
+    fn _verify_checksums(&mut self, migrations: &[Migration]) -> Result<(), MigrateError> {
+        let rows = self.client.query(
+            "SELECT migration_id, checksum FROM history WHERE module = $1 AND checksum IS NOT NULL",
+            &[&self.module]
+        )?;
+        for row in rows {
+            let migration_id: String = row.get("migration_id");
+            let checksum: String = row.get("checksum");
+            if let Some(migration) = migrations.iter().find(|m| m.revision == migration_id) {
+                if migration.checksum()? != checksum {
+                    println!("Warning: migration {} has changed since it was applied", migration_id);
                 }
             }
         }
-        false
+        Ok(())
     }
 
-    fn _get_migration_details(&self, path: &PathBuf) -> (String, String, String, String) {
-        let mut split: Vec<&str> = path.to_str().expect("REASON").split('/').collect();
-        split = split[1].split('_').collect();
-        let date = split[0];
-        let time = split[1];
-        let migration_id = split[2];
-        let _migration_name = &split[3..split.len()-1];
-        let migration_name = _migration_name.join(" ");
-        (date.to_string(), time.to_string(), migration_id.to_string(), migration_name)
+    fn _sort_migrations(&self, migrations: &mut [Migration], asc: bool) {
+        migrations.sort_by(|a, b| {
+            if asc {
+                return a.order.cmp(&b.order);
+            }
+            b.order.cmp(&a.order)
+        });
     }
 
-    fn _get_down_migration_id(&self, file_path: &str) -> Option<String> {
-        let file = File::open(file_path).unwrap();
-        let reader = io::BufReader::new(file);
+    /// Resolves a revision, revision prefix, name or reference such as `head~2` or `current+1`;
+    /// `None` is base. See `revision::resolve`.
+    fn _resolve_target(&mut self, migrations: &[Migration], target: &str) -> Result<Option<String>, MigrateError> {
+        revision::resolve(migrations, target, || self.current_revision())
+    }
 
-        for line in reader.lines() {
-            let line = line.unwrap();
-            if line.starts_with("-- Down Revision: ") {
-                let revision = line.trim_start_matches("-- Down Revision: ").to_string();
-                return Some(revision)
-            }
+    fn _if_target_exists(&mut self, target: &str) -> bool {
+        let Ok(migrations) = self._load_migrations() else {
+            return false;
+        };
+        self._resolve_target(&migrations, target).is_ok()
+    }
+
+    /// Number of migrations up to and including the current one.
+    fn _applied_count(&mut self, migrations: &[Migration]) -> usize {
+        match self._get_current() {
+            Some(current) => migrations.iter().position(|m| m.revision == current).map_or(0, |i| i + 1),
+            None => 0,
         }
-        None
+    }
+
+    fn _is_applied(&mut self, migration_id: &str) -> Result<bool, Error> {
+        let module = self.module.clone();
+        self._is_applied_in(&module, migration_id)
+    }
+
+    /// Whether a `-- Depends On` entry, `revision` or `module:revision`, is applied.
+    fn _is_dependency_applied(&mut self, dependency: &str) -> Result<bool, Error> {
+        let qualified = self._qualify(dependency);
+        let (module, migration_id) = qualified.split_once(':').unwrap();
+        self._is_applied_in(module, migration_id)
+    }
+
+    fn _is_applied_in(&mut self, module: &str, migration_id: &str) -> Result<bool, Error> {
+        let row = self.client.query_opt(
+            "SELECT 1 FROM history WHERE module = $1 AND migration_id = $2",
+            &[&module, &migration_id],
+        )?;
+        Ok(row.is_some())
+    }
+
+    fn _if_count_valid(&mut self, count: &i32, upgrade: bool) -> bool {
+        let Ok(migrations) = self._load_migrations() else {
+            return false;
+        };
+        let applied = self._applied_count(&migrations);
+        let available = if upgrade { migrations.len() - applied } else { applied };
+        *count > 0 && *count as usize <= available
     }
 
     fn _get_history(&mut self) -> (Option<String>, Option<String>) {
         match self.client.query_opt(
-            "SELECT * FROM history ORDER BY ID DESC LIMIT 1",
-            &[]
+            "SELECT * FROM history WHERE module = $1 ORDER BY ID DESC LIMIT 1",
+            &[&self.module]
         ) {
             Ok(Some(row)) => {
                 let migration_id: Option<String> = row.get("migration_id");
@@ -458,17 +1424,23 @@ impl DbClient {
         dotenv().ok();
         env::var("MIGRATION_DIR").unwrap_or_else(|_| "migrations".to_string())
     }
+
+    pub fn get_seed_dir() -> String {
+        dotenv().ok();
+        env::var("SEED_DIR").unwrap_or_else(|_| "seeds".to_string())
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::needless_borrow)]
 mod tests {
     use super::*;
-    use crate::setup::get_db_client;
+    use crate::setup::{get_db_client, lock_db_client, lock_db_client_with_migrations, write_migration, TempDir};
 
     #[test]
     fn test_get_head() {
         let db_client = get_db_client().lock().unwrap();
-        let head = db_client._get_head();
+        let head = db_client._get_head().unwrap();
         assert_eq!(head.unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
     }
 
@@ -486,18 +1458,16 @@ mod tests {
         let mut current = db_client._get_current();
         assert_eq!(current.is_none(), true);
 
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-        db_client._sort_paths(&mut paths, true);
-        let _ = db_client._upgrade_head(&mut paths);
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, true);
+        let _ = db_client._upgrade_head(&migrations);
 
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
 
-        db_client._sort_paths(&mut paths, false);
+        db_client._sort_migrations(&mut migrations, false);
 
-        let _ = db_client._migrate_count(&mut paths, false, &2);
+        let _ = db_client._migrate_count(&migrations, false, &2);
         current = db_client._get_current();
         assert_eq!(current.is_none(), true);
     }
@@ -509,42 +1479,64 @@ mod tests {
         let mut current = db_client._get_current();
         assert_eq!(current.is_none(), true);
 
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-        db_client._sort_paths(&mut paths, true);
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, true);
 
-        let _ = db_client._migrate_target(&mut paths, true, "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
+        let _ = db_client._migrate_target(&migrations, true, "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
 
-        db_client._sort_paths(&mut paths, false);
+        db_client._sort_migrations(&mut migrations, false);
 
-        let _ = db_client._migrate_target(&mut paths, false, "f44e620f-60e0-4470-8904-44b4022b11a5");
+        let _ = db_client._migrate_target(&migrations, false, "f44e620f-60e0-4470-8904-44b4022b11a5");
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
 
-        let _ = db_client._migrate_count(&mut paths, false, &1);
+        let _ = db_client._migrate_count(&migrations, false, &1);
         current = db_client._get_current();
         assert_eq!(current.is_none(), true);
 
-        db_client._sort_paths(&mut paths, true);
+        db_client._sort_migrations(&mut migrations, true);
 
-        let _ = db_client._migrate_target(&mut paths, true, "f44e620f-60e0-4470-8904-44b4022b11a5");
+        let _ = db_client._migrate_target(&migrations, true, "f44e620f-60e0-4470-8904-44b4022b11a5");
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
 
-        let _ = db_client._migrate_target(&mut paths, true, "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
+        let _ = db_client._migrate_target(&migrations, true, "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
 
-        db_client._sort_paths(&mut paths, false);
+        db_client._sort_migrations(&mut migrations, false);
 
-        let _ = db_client._migrate_count(&mut paths, false, &2);
+        let _ = db_client._migrate_count(&migrations, false, &2);
         current = db_client._get_current();
         assert_eq!(current.is_none(), true);
     }
 
+    #[test]
+    fn test_migrate_target_references() {
+        let mut db_client = lock_db_client();
+
+        let substring = db_client.run_migrations(true, false, Some("2024-07-01"), None);
+        assert!(matches!(substring, Err(MigrateError::UnknownRevision(r)) if r == "2024-07-01"));
+        assert!(db_client._get_current().is_none());
+
+        db_client.run_migrations(true, false, Some("head~1"), None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
+
+        db_client.run_migrations(true, false, Some("current+1"), None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
+
+        // Upgrading to a revision before the current one runs nothing.
+        db_client.run_migrations(true, false, Some("current-1"), None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
+
+        let ambiguous = db_client.run_migrations(false, false, Some(""), None);
+        assert!(matches!(ambiguous, Err(MigrateError::AmbiguousRevision { .. })));
+        db_client.run_migrations(false, false, Some("base"), None).unwrap();
+        assert!(db_client._get_current().is_none());
+    }
+
     #[test]
     fn test_migrate_count() {
         let mut db_client = get_db_client().lock().unwrap();
@@ -552,38 +1544,36 @@ mod tests {
         let mut current = db_client._get_current();
         assert_eq!(current.is_none(), true);
 
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-        db_client._sort_paths(&mut paths, true);
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, true);
 
-        let _ = db_client._migrate_count(&mut paths, true, &2);
+        let _ = db_client._migrate_count(&migrations, true, &2);
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
 
-        db_client._sort_paths(&mut paths, false);
+        db_client._sort_migrations(&mut migrations, false);
 
-        let _ = db_client._migrate_count(&mut paths, false, &2);
+        let _ = db_client._migrate_count(&migrations, false, &2);
         current = db_client._get_current();
         assert_eq!(current.is_none(), true);
 
-        db_client._sort_paths(&mut paths, true);
+        db_client._sort_migrations(&mut migrations, true);
 
-        let _ = db_client._migrate_count(&mut paths, true, &1);
+        let _ = db_client._migrate_count(&migrations, true, &1);
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
 
-        let _ = db_client._migrate_count(&mut paths, true, &1);
+        let _ = db_client._migrate_count(&migrations, true, &1);
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
 
-        db_client._sort_paths(&mut paths, false);
+        db_client._sort_migrations(&mut migrations, false);
 
-        let _ = db_client._migrate_count(&mut paths, false, &1);
+        let _ = db_client._migrate_count(&migrations, false, &1);
         current = db_client._get_current();
         assert_eq!(current.unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
 
-        let _ = db_client._migrate_count(&mut paths, false, &1);
+        let _ = db_client._migrate_count(&migrations, false, &1);
         current = db_client._get_current();
         assert_eq!(current.is_none(), true);
     }
@@ -606,7 +1596,9 @@ mod tests {
         let mut db_client = get_db_client().lock().unwrap();
 
         let (migration_id, _) = db_client._get_history();
-        let _ = db_client._remove_from_history(migration_id.as_deref().unwrap());
+        if let Some(migration_id) = migration_id {
+            let _ = db_client._remove_from_history(&migration_id);
+        }
 
         let _ = db_client._save_history(&"622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd", &"add_wallet");
         let (migration_id, migration_name) = db_client._get_history();
@@ -624,28 +1616,24 @@ mod tests {
     #[test]
     fn test_sort_paths_asc() {
         let db_client = get_db_client().lock().unwrap();
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-        db_client._sort_paths(&mut paths, true);
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, true);
 
-        assert_eq!(paths[0].to_str().unwrap(), "migrations/2024-07-01_13:01:16_f44e620f-60e0-4470-8904-44b4022b11a5_add_users_up.sql");
+        assert_eq!(migrations[0].up_files[0].to_str().unwrap(), "migrations/2024-07-01_13:01:16_f44e620f-60e0-4470-8904-44b4022b11a5_add_users_up.sql");
     }
 
     #[test]
     fn test_sort_paths_desc() {
         let db_client = get_db_client().lock().unwrap();
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-        db_client._sort_paths(&mut paths, false);
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, false);
 
-        assert_eq!(paths[0].to_str().unwrap(), "migrations/2024-07-02_10:49:20_622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd_add_wallet_down.sql");
+        assert_eq!(migrations[0].down_files[0].to_str().unwrap(), "migrations/2024-07-02_10:49:20_622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd_add_wallet_down.sql");
     }
 
     #[test]
     fn test_if_target_exists() {
-        let db_client = get_db_client().lock().unwrap();
+        let mut db_client = get_db_client().lock().unwrap();
         let target = "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd";
         let exists = db_client._if_target_exists(target);
         assert_eq!(exists, true);
@@ -675,31 +1663,476 @@ mod tests {
     #[test]
     fn test_get_migration_details() {
         let db_client = get_db_client().lock().unwrap();
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-
-        db_client._sort_paths(&mut paths, true);
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, true);
 
-        let (migration_date, migration_time, migration_id, migration_name) = db_client._get_migration_details(&paths[0]);
-        assert_eq!(migration_date, "2024-07-01");
-        assert_eq!(migration_time, "13:01:16");
-        assert_eq!(migration_id, "f44e620f-60e0-4470-8904-44b4022b11a5");
-        assert_eq!(migration_name, "add users");
+        assert_eq!(migrations[0].sort_key, "2024-07-01_13:01:16_f44e620f-60e0-4470-8904-44b4022b11a5_add_users");
+        assert_eq!(migrations[0].revision, "f44e620f-60e0-4470-8904-44b4022b11a5");
+        assert_eq!(migrations[0].name, "add users");
     }
 
     #[test]
     fn test_get_down_migration_id() {
         let db_client = get_db_client().lock().unwrap();
-        let mut paths: Vec<PathBuf> = fs::read_dir(DbClient::get_migration_dir()).unwrap()
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, true);
+
+        assert!(migrations[0].down_revision().is_none());
+        assert_eq!(migrations[1].down_revision().unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
+    }
+
+    #[test]
+    fn test_directory_migration() {
+        let mut db_client = lock_db_client_with_migrations();
+        let revision_dir = db_client.dir().join("2024-07-03_09:00:00_5b0e7d4c-2f1a-4c1e-9b5e-0c7f0d3e2a11_add_catalog");
+        fs::create_dir_all(&revision_dir).unwrap();
+        fs::write(revision_dir.join("up.sql"), "-- Revision: 5b0e7d4c-2f1a-4c1e-9b5e-0c7f0d3e2a11\n-- Down Revision: None\nCREATE SCHEMA catalog;\n").unwrap();
+        fs::write(revision_dir.join("up.01-tables.sql"), "CREATE TABLE catalog.items (id INT);\n").unwrap();
+        fs::write(revision_dir.join("up.02-seed.sql"), "INSERT INTO catalog.items VALUES (1), (2);\n").unwrap();
+        fs::write(revision_dir.join("down.sql"), "DROP SCHEMA catalog CASCADE;\n").unwrap();
+
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "5b0e7d4c-2f1a-4c1e-9b5e-0c7f0d3e2a11");
+        let items: i64 = db_client.client.query_one("SELECT count(*) FROM catalog.items", &[]).unwrap().get(0);
+        assert_eq!(items, 2);
+
+        db_client.run_migrations(false, false, None, Some(&1)).unwrap();
+        assert!(db_client._get_current().is_none());
+    }
+
+    #[test]
+    fn test_placeholders_and_dry_run() {
+        let mut db_client = lock_db_client_with_migrations();
+        db_client.write_migration("V0001", None, "add_audit", "CREATE TABLE ${table} (id INT);\n", "DROP TABLE ${table};\n");
+
+        let undefined = db_client.run_migrations(true, true, None, None);
+        assert!(matches!(undefined, Err(MigrateError::UndefinedPlaceholder(name)) if name == "table"));
+        assert!(db_client._get_current().is_none());
+
+        db_client.set_var("table", "audit_log");
+        db_client.set_dry_run(true);
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert!(db_client._get_current().is_none());
+
+        db_client.set_dry_run(false);
+        db_client.run_migrations(true, true, None, None).unwrap();
+        let audit_log: Option<String> = db_client.client.query_one("SELECT to_regclass('audit_log')::TEXT", &[]).unwrap().get(0);
+        assert_eq!(audit_log.as_deref(), Some("audit_log"));
+    }
+
+    #[test]
+    fn test_repeatable_migrations() {
+        let mut db_client = lock_db_client_with_migrations();
+        let repeatable_dir = db_client.dir().join("repeatable");
+        fs::create_dir_all(&repeatable_dir).unwrap();
+        fs::write(repeatable_dir.join("R02_answer_view.sql"), "CREATE OR REPLACE VIEW answer_view AS SELECT answer() AS answer;\n").unwrap();
+
+        let mut last_applied_at = None;
+        for (answer, reapplied) in [(41, true), (41, false), (42, true)] {
+            fs::write(repeatable_dir.join("R01_answer.sql"), format!("CREATE OR REPLACE FUNCTION answer() RETURNS INT AS $$ SELECT {} $$ LANGUAGE sql;\n", answer)).unwrap();
+            db_client.run_migrations(true, true, None, None).unwrap();
+            let current: i32 = db_client.client.query_one("SELECT answer FROM answer_view", &[]).unwrap().get(0);
+            assert_eq!(current, answer);
+            let applied_at: chrono::NaiveDateTime = db_client.client.query_one(
+                "SELECT applied_at FROM repeatable_migrations WHERE name = 'R01_answer.sql'", &[]
+            ).unwrap().get(0);
+            assert_eq!(last_applied_at != Some(applied_at), reapplied);
+            last_applied_at = Some(applied_at);
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let mut db_client = lock_db_client_with_migrations();
+        db_client.write_migration("V0001", None, "add_t", "CREATE TABLE hooked (id INT);\n", "DROP TABLE hooked;\n");
+        db_client.write_migration("V0002", Some("V0001"), "broken", "SELECT * FROM missing_table;\n", "");
+        let hooks_dir = db_client.dir().join("hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        fs::write(hooks_dir.join("before_all.sql"), "CREATE TABLE IF NOT EXISTS hook_log (entry TEXT);\n").unwrap();
+        fs::write(hooks_dir.join("after_each.sql"), "INSERT INTO hook_log VALUES ('${direction} ${revision}');\n").unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        for point in [HookPoint::BeforeAll, HookPoint::BeforeEach, HookPoint::AfterEach, HookPoint::AfterAll, HookPoint::OnError] {
+            let events = events.clone();
+            db_client.add_hook(point, move |_client, context| {
+                let revision = context.revision.clone().unwrap_or_else(|| "None".to_string());
+                events.lock().unwrap().push(format!("{} {} {}", context.point.name(), context.direction, revision));
+                Ok(())
+            });
+        }
+        let take_events = || events.lock().unwrap().drain(..).collect::<Vec<String>>();
+
+        db_client.run_migrations(true, false, None, Some(&1)).unwrap();
+        assert_eq!(take_events(), vec![
+            "before_all upgrade None",
+            "before_each upgrade V0001",
+            "after_each upgrade V0001",
+            "after_all upgrade V0001",
+        ]);
+
+        assert!(db_client.run_migrations(true, true, None, None).is_err());
+        assert_eq!(take_events(), vec!["before_all upgrade V0001", "before_each upgrade V0002", "on_error upgrade V0001"]);
+
+        db_client.run_migrations(false, false, None, Some(&1)).unwrap();
+        assert_eq!(take_events(), vec![
+            "before_all downgrade V0001",
+            "before_each downgrade V0001",
+            "after_each downgrade V0001",
+            "after_all downgrade None",
+        ]);
+
+        let log: Vec<String> = db_client.client.query("SELECT entry FROM hook_log", &[]).unwrap()
+            .iter().map(|row| row.get(0)).collect();
+        assert_eq!(log, vec!["upgrade V0001", "downgrade V0001"]);
+    }
+
+    #[test]
+    fn test_autogenerate_migration() {
+        let mut db_client = lock_db_client_with_migrations();
+        let migration_dir = db_client.dir().to_path_buf();
+        for entry in fs::read_dir(DbClient::get_migration_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, migration_dir.join(path.file_name().unwrap())).unwrap();
+        }
+        db_client.set_naming_scheme("hash");
+        db_client.run_migrations(true, true, None, None).unwrap();
+
+        let schema_file = migration_dir.join("schema.sql");
+        let desired_sql = format!(
+            "{}\nCREATE TABLE public.orders (id integer NOT NULL, total numeric);\nALTER TABLE public.users ADD COLUMN email text;\n",
+            Schema::introspect(&mut db_client.client).unwrap().to_sql()
+        );
+        fs::write(&schema_file, desired_sql).unwrap();
+
+        db_client.autogenerate_migration("add_orders", schema_file.to_str()).unwrap();
+        let up_sql = fs::read_dir(&migration_dir).unwrap()
             .map(|entry| entry.unwrap().path())
-            .collect();
-        db_client._sort_paths(&mut paths, true);
+            .find(|path| path.to_str().unwrap().ends_with("_add_orders_up.sql"))
+            .map(|path| fs::read_to_string(path).unwrap())
+            .unwrap();
+        assert!(up_sql.contains("CREATE TABLE public.orders (\n    id integer NOT NULL,\n    total numeric\n);"));
+        assert!(up_sql.contains("ALTER TABLE public.users ADD COLUMN email text;"));
+
+        db_client.run_migrations(true, true, None, None).unwrap();
+        let upgraded = Schema::introspect(&mut db_client.client).unwrap();
+        let scratch = ScratchDatabase::create(&db_client.database_url).unwrap();
+        let mut client = scratch.connect().unwrap();
+        client.batch_execute(&fs::read_to_string(&schema_file).unwrap()).unwrap();
+        let desired = Schema::introspect(&mut client).unwrap();
+        assert_eq!(diff::describe(&upgraded, &desired), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_check_roundtrip() {
+        let mut db_client = lock_db_client();
+        assert!(db_client.check_roundtrip().unwrap());
+        assert!(db_client._get_current().is_none());
+    }
+
+    #[test]
+    fn test_check_roundtrip_incomplete_down() {
+        let mut db_client = lock_db_client_with_migrations();
+        db_client.write_migration("V0001", None, "add_items", "CREATE TABLE items (id INT);\n", "DROP TABLE items;\n");
+        db_client.write_migration(
+            "V0002", Some("V0001"), "add_tags",
+            "CREATE SEQUENCE tag_ids AS integer; ALTER TABLE items ADD COLUMN tag TEXT;\n",
+            "ALTER TABLE items DROP COLUMN tag;\n",
+        );
+
+        assert!(!db_client.check_roundtrip().unwrap());
+        assert!(db_client._get_current().is_none());
+    }
+
+    #[test]
+    fn test_validate_shadow() {
+        let mut db_client = lock_db_client();
+
+        db_client.run_migrations(true, false, None, Some(&1)).unwrap();
+        db_client.client.batch_execute("CREATE TABLE out_of_band (id INT)").unwrap();
+        assert!(!db_client.validate_shadow().unwrap());
+
+        db_client.set_shadow_check(true);
+        let refused = db_client.run_migrations(true, true, None, None);
+        assert!(matches!(refused, Err(MigrateError::ShadowMismatch(differences)) if differences == vec!["table public.out_of_band added"]));
+        assert_eq!(db_client._get_current().unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
+
+        // A configured shadow database is emptied first, including schemas that need quoting.
+        db_client.client.batch_execute("DROP TABLE out_of_band").unwrap();
+        let shadow = ScratchDatabase::create(&db_client.database_url).unwrap();
+        shadow.connect().unwrap().batch_execute("CREATE SCHEMA \"Mixed Leftover\"; CREATE TABLE leftover_table (id INT);").unwrap();
+        db_client.set_shadow_url(Some(shadow.url()));
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut db_client = lock_db_client_with_migrations();
+        db_client.write_migration("V0001", None, "base", "CREATE TABLE base (id INT);\n", "DROP TABLE base;\n");
+        db_client.write_migration("V0003", Some("V0001"), "feature", "CREATE TABLE feature (id INT);\n", "DROP TABLE feature;\n");
+        db_client.run_migrations(true, true, None, None).unwrap();
+
+        db_client.write_migration("V0002", Some("V0001"), "hotfix", "CREATE TABLE hotfix (id INT);\n", "DROP TABLE hotfix;\n");
+        let mut migrations = db_client._load_migrations().unwrap();
+        db_client._sort_migrations(&mut migrations, true);
+        let out_of_order: Vec<String> = db_client._out_of_order(&migrations).unwrap().iter().map(|m| m.revision.clone()).collect();
+        assert_eq!(out_of_order, vec!["V0002"]);
+        let refused = db_client.run_migrations(true, true, None, None);
+        assert!(matches!(refused, Err(MigrateError::OutOfOrder(revisions)) if revisions == vec!["V0002"]));
+
+        db_client.set_allow_out_of_order(true);
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "V0003");
+        let hotfix: Option<String> = db_client.client.query_one("SELECT to_regclass('hotfix')::TEXT", &[]).unwrap().get(0);
+        assert_eq!(hotfix.as_deref(), Some("hotfix"));
+        assert!(db_client._out_of_order(&migrations).unwrap().is_empty());
+
+        db_client.run_migrations(false, false, None, Some(&3)).unwrap();
+        assert!(db_client._get_current().is_none());
+    }
+
+    #[test]
+    fn test_depends_on() {
+        let mut db_client = lock_db_client_with_migrations();
+        db_client.write_migration("V0001", None, "users", "CREATE TABLE dep_users (id INT);\n", "DROP TABLE dep_users;\n");
+        db_client.write_migration("V0002", Some("V0001"), "wallets", "-- Depends On: billing:billing_0001\n", "");
+
+        let missing = db_client.run_migrations(true, true, None, None);
+        assert!(matches!(missing, Err(MigrateError::MissingDependency { revision, dependency }) if revision == "V0002" && dependency == "billing:billing_0001"));
+        assert_eq!(db_client._get_current().unwrap(), "V0001");
+
+        // Another module that depends on V0001 was applied.
+        db_client.client.execute(
+            "INSERT INTO history (migration_id, name, module, depends_on) VALUES ('billing_0001', 'billing', 'billing', '{default:V0001}')",
+            &[],
+        ).unwrap();
+        db_client.run_migrations(true, true, None, None).unwrap();
+        db_client.run_migrations(false, false, None, Some(&1)).unwrap();
+        let required = db_client.run_migrations(false, false, None, Some(&1));
+        assert!(matches!(required, Err(MigrateError::RequiredBy { revision, dependents }) if revision == "V0001" && dependents == vec!["billing:billing_0001"]));
+        assert_eq!(db_client._get_current().unwrap(), "V0001");
+
+        db_client.client.execute("DELETE FROM history WHERE module = 'billing'", &[]).unwrap();
+        db_client.run_migrations(false, false, None, Some(&1)).unwrap();
+        assert!(db_client._get_current().is_none());
+    }
+
+    #[test]
+    fn test_squash_applied_migrations() {
+        let mut db_client = lock_db_client_with_migrations();
+        db_client.write_migration("V0001", None, "sq_a", "CREATE TABLE sq_a (id INT);\n", "DROP TABLE sq_a;\n");
+        db_client.write_migration("V0002", Some("V0001"), "sq_b", "CREATE TABLE sq_b (id INT);\n", "DROP TABLE sq_b;\n");
+        db_client.write_migration("V0003", Some("V0002"), "sq_c", "CREATE TABLE sq_c (id INT);\n", "DROP TABLE sq_c;\n");
+        db_client.write_migration("V0004", Some("V0003"), "sq_d", "CREATE TABLE sq_d (id INT);\n", "DROP TABLE sq_d;\n");
+        db_client.set_naming_scheme("sequential");
+        db_client.run_migrations(true, false, Some("V0003"), None).unwrap();
+
+        db_client.squash_migrations("V0001", "current-1", None).unwrap();
+        assert!(db_client.dir().join("V0005_squashed_up.sql").is_file());
+
+        // Only migrating adopts the squashed migration in place of the ones it replaces.
+        db_client.get_status().unwrap();
+        assert!(!db_client._is_applied("V0005").unwrap());
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "V0004");
+        assert!(db_client._is_applied("V0005").unwrap());
+        assert!(!db_client._is_applied("V0001").unwrap());
+
+        db_client.run_migrations(false, false, None, Some(&3)).unwrap();
+        assert!(db_client._get_current().is_none());
+        let sq_a: Option<String> = db_client.client.query_one("SELECT to_regclass('sq_a')::TEXT", &[]).unwrap().get(0);
+        assert!(sq_a.is_none());
+    }
+
+    #[test]
+    fn test_import_migrations() {
+        let mut db_client = lock_db_client_with_migrations();
+        let sqlx_dir = TempDir::new();
+        fs::write(sqlx_dir.join("20240101090000_add_imported.up.sql"), "CREATE TABLE imported (id INT);\n").unwrap();
+        fs::write(sqlx_dir.join("20240101090000_add_imported.down.sql"), "DROP TABLE imported;\n").unwrap();
+        fs::write(sqlx_dir.join("20240102090000_add_imported_items.up.sql"), "CREATE TABLE imported_items (id INT);\n").unwrap();
+        fs::write(sqlx_dir.join("20240102090000_add_imported_items.down.sql"), "DROP TABLE imported_items;\n").unwrap();
+        // sqlx already applied the first migration.
+        db_client.client.batch_execute(
+            "CREATE TABLE _sqlx_migrations (version BIGINT PRIMARY KEY, description TEXT NOT NULL, success BOOLEAN NOT NULL);\
+            INSERT INTO _sqlx_migrations VALUES (20240101090000, 'add imported', true);\
+            CREATE TABLE imported (id INT);"
+        ).unwrap();
+
+        db_client.import_migrations(Source::Sqlx, &sqlx_dir).unwrap();
+        let up_sql = fs::read_to_string(db_client.dir().join("20240102090000_add_imported_items_up.sql")).unwrap();
+        assert_eq!(up_sql, "-- SQL commands to upgrade\n-- Revision: 20240102090000\n-- Down Revision: 20240101090000\n\
+            -- Name: add_imported_items\n\nCREATE TABLE imported_items (id INT);\n");
+        assert_eq!(db_client._get_current().unwrap(), "20240101090000");
+        assert!(db_client._is_applied("20240101090000").unwrap());
+        assert!(!db_client._is_applied("20240102090000").unwrap());
+
+        // Importing again skips the migrations already imported.
+        db_client.import_migrations(Source::Sqlx, &sqlx_dir).unwrap();
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "20240102090000");
+        db_client.run_migrations(false, false, None, Some(&2)).unwrap();
+    }
+
+    #[test]
+    fn test_show_migration() {
+        let mut db_client = lock_db_client();
+        assert!(db_client.show_migration("f44e620f").is_ok());
+        assert!(db_client.show_migration("add_wallet").is_ok());
+        assert!(db_client.show_migration("add wallet").is_ok());
+        assert!(matches!(db_client.show_migration("2024"), Err(MigrateError::UnknownRevision(_))));
+    }
 
-        let mut down_migration_id = db_client._get_down_migration_id(paths[0].to_str().unwrap());
-        assert_eq!(down_migration_id.unwrap(), "None");
+    #[test]
+    fn test_modules() {
+        let mut db_client = lock_db_client();
+        let root = TempDir::new();
+        for (module, table) in [("auth", "accounts"), ("billing", "invoices")] {
+            let dir = root.join(module);
+            fs::create_dir_all(&dir).unwrap();
+            write_migration(&dir, &format!("{}_1", module), None, "init", &format!("CREATE TABLE {} (id INT);\n", table), &format!("DROP TABLE {};\n", table));
+            db_client.add_module(module, dir.to_str().unwrap());
+        }
+        assert!(matches!(db_client.set_module("shipping"), Err(MigrateError::UnknownModule(module)) if module == "shipping"));
+        assert_eq!(db_client.modules(), vec![DEFAULT_MODULE, "auth", "billing"]);
+
+        db_client.set_module("billing").unwrap();
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().as_deref(), Some("billing_1"));
+
+        db_client.set_module("auth").unwrap();
+        assert!(db_client._get_current().is_none());
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().as_deref(), Some("auth_1"));
 
-        down_migration_id = db_client._get_down_migration_id(paths[2].to_str().unwrap());
-        assert_eq!(down_migration_id.unwrap(), "f44e620f-60e0-4470-8904-44b4022b11a5");
+        db_client.set_module(DEFAULT_MODULE).unwrap();
+        assert!(db_client._get_current().is_none());
+    }
+
+    #[test]
+    fn test_modules_share_revision_ids() {
+        let mut db_client = lock_db_client();
+        let root = TempDir::new();
+        for (module, table) in [("auth", "shared_accounts"), ("billing", "shared_invoices")] {
+            let dir = root.join(module);
+            fs::create_dir_all(&dir).unwrap();
+            write_migration(&dir, "V0001", None, "init", &format!("CREATE TABLE {} (id INT);\n", table), &format!("DROP TABLE {};\n", table));
+            db_client.add_module(module, dir.to_str().unwrap());
+        }
+        write_migration(&root.join("billing"), "V0002", Some("V0001"), "owner", "-- Depends On: auth:V0001\n", "");
+
+        db_client.set_module("auth").unwrap();
+        db_client.run_migrations(true, true, None, None).unwrap();
+        db_client.set_module("billing").unwrap();
+        db_client.run_migrations(true, true, None, None).unwrap();
+        assert_eq!(db_client._get_current().as_deref(), Some("V0002"));
+        let applied: i64 = db_client.client
+            .query_one("SELECT count(*) FROM history WHERE migration_id = 'V0001'", &[]).unwrap().get(0);
+        assert_eq!(applied, 2);
+
+        db_client.set_module("auth").unwrap();
+        let required = db_client.run_migrations(false, false, None, Some(&1));
+        assert!(matches!(required, Err(MigrateError::RequiredBy { revision, dependents }) if revision == "V0001" && dependents == vec!["billing:V0002"]));
+
+        db_client.set_module("billing").unwrap();
+        db_client.run_migrations(false, false, None, Some(&2)).unwrap();
+        db_client.set_module("auth").unwrap();
+        assert!(db_client._is_applied("V0001").unwrap());
+        db_client.run_migrations(false, false, None, Some(&1)).unwrap();
+        assert!(!db_client._is_applied("V0001").unwrap());
+    }
+
+    #[test]
+    fn test_tenants() {
+        let mut db_client = lock_db_client();
+        db_client.client.batch_execute(
+            "CREATE SCHEMA pgm_tenant_a; CREATE SCHEMA pgm_tenant_b; CREATE SCHEMA pgm_tenant_c; \
+            CREATE TABLE pgm_tenant_c.users (id INT);"
+        ).unwrap();
+        db_client.set_tenants(TenantsConfig {
+            schemas: vec!["pgm_tenant_new".to_string()],
+            pattern: Some("pgm_tenant_%".to_string()),
+            ..TenantsConfig::default()
+        });
+        assert_eq!(db_client.tenant_schemas().unwrap(), vec!["pgm_tenant_a", "pgm_tenant_b", "pgm_tenant_c", "pgm_tenant_new"]);
+
+        // The tenant whose users table already exists fails without stopping the others.
+        let upgrade = db_client.run_tenant_migrations(true, true, None, None, Some(2));
+        assert!(matches!(upgrade, Err(MigrateError::TenantFailures(failures)) if failures.len() == 1 && failures[0].0 == "pgm_tenant_c"));
+        for tenant in ["pgm_tenant_a", "pgm_tenant_new"] {
+            let current: Option<String> = db_client.client
+                .query_one(&format!("SELECT migration_id FROM {}.migrations", tenant), &[]).unwrap().get(0);
+            assert_eq!(current.as_deref(), Some("622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd"));
+        }
+        let wallets: Option<String> = db_client.client.query_one("SELECT to_regclass('pgm_tenant_b.wallets')::TEXT", &[]).unwrap().get(0);
+        assert_eq!(wallets.as_deref(), Some("pgm_tenant_b.wallets"));
+        assert!(db_client._get_current().is_none());
+
+        // Reading the status creates neither missing tenant schemas nor bookkeeping tables.
+        db_client.client.batch_execute("CREATE SCHEMA pgm_tenant_empty").unwrap();
+        db_client.set_tenants(TenantsConfig {
+            schemas: vec!["pgm_tenant_a".to_string(), "pgm_tenant_empty".to_string(), "pgm_tenant_missing".to_string()],
+            ..TenantsConfig::default()
+        });
+        db_client.get_status_all_tenants().unwrap();
+        let created: i64 = db_client.client.query_one(
+            "SELECT count(*) FROM pg_namespace WHERE nspname = 'pgm_tenant_missing'", &[]
+        ).unwrap().get(0);
+        assert_eq!(created, 0);
+        let tenant_empty: Option<String> = db_client.client.query_one("SELECT to_regclass('pgm_tenant_empty.migrations')::TEXT", &[]).unwrap().get(0);
+        assert!(tenant_empty.is_none());
+    }
+
+    #[test]
+    fn test_tenant_bookkeeping() {
+        let db_client = lock_db_client();
+        let scratch = ScratchDatabase::create(&db_client.database_url).unwrap();
+        let mut client = scratch.connect().unwrap();
+        let mut exists = |table: &str| -> bool {
+            client.query_one("SELECT to_regclass($1) IS NOT NULL", &[&table]).unwrap().get(0)
+        };
+
+        let read = DbClient::new_for_tenant(scratch.url(), "pgm_tenant", false);
+        assert!(matches!(read, Err(MigrateError::UninitialisedTenant(schema)) if schema == "pgm_tenant"));
+        assert!(!exists("public.migrations"));
+
+        let mut tenant = DbClient::new_for_tenant(scratch.url(), "pgm_tenant", true).unwrap();
+        assert!(tenant.run_migrations(true, true, None, None).is_ok());
+        assert!(exists("pgm_tenant.migrations"));
+        assert!(!exists("public.migrations"));
+        drop(tenant);
+
+        let mut reader = DbClient::new_for_tenant(scratch.url(), "pgm_tenant", false).unwrap();
+        assert_eq!(reader._get_current().as_deref(), Some("622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd"));
+    }
+
+    #[test]
+    fn test_seeds() {
+        let mut db_client = lock_db_client();
+        let seed_dir = TempDir::new();
+        fs::create_dir_all(seed_dir.join("dev")).unwrap();
+        fs::write(seed_dir.join("01_users.sql"), "-- Requires: f44e620f-60e0-4470-8904-44b4022b11a5\nINSERT INTO users (username, password) VALUES ('admin', 'admin');\n").unwrap();
+        fs::write(seed_dir.join("dev").join("01_wallets.sql"), "-- Requires: 622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd\n\
+            INSERT INTO wallets (user_id, address, encrypted_seed_phrase, encrypted_private_key) \
+            SELECT id, 'addr', 'seed', 'key' FROM users;\n").unwrap();
+        db_client.set_seed_dir(seed_dir.to_str().unwrap());
+
+        // The wallets seed waits for the migration it requires.
+        db_client.run_migrations(true, false, None, Some(&1)).unwrap();
+        assert_eq!(db_client.run_seeds(Some("dev")).unwrap(), vec!["01_users.sql"]);
+
+        db_client.reset().unwrap();
+        assert_eq!(db_client._get_current().unwrap(), "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
+        let seeds: i64 = db_client.client.query_one("SELECT count(*) FROM seeds", &[]).unwrap().get(0);
+        assert_eq!(seeds, 0);
+
+        assert_eq!(db_client.run_seeds(Some("dev")).unwrap(), vec!["01_users.sql", "dev/01_wallets.sql"]);
+        assert!(db_client.run_seeds(Some("dev")).unwrap().is_empty());
+        let users: i64 = db_client.client.query_one("SELECT count(*) FROM users", &[]).unwrap().get(0);
+        assert_eq!(users, 1);
+        let wallets: i64 = db_client.client.query_one("SELECT count(*) FROM wallets", &[]).unwrap().get(0);
+        assert_eq!(wallets, 1);
     }
 }
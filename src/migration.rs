@@ -0,0 +1,522 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::naming::{MigrationName, NamingScheme};
+
+/// Contents of the optional `meta.toml` of a directory migration.
+#[derive(Debug, Default, Deserialize)]
+pub struct Meta {
+    pub revision: Option<String>,
+    pub down_revisions: Option<Vec<String>>,
+    pub depends_on: Option<Vec<String>>,
+    pub replaces: Option<Vec<String>>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+/// A single revision found in the migrations directory.
+///
+/// A migration is either a pair of `<stem>_up.sql` / `<stem>_down.sql` files or a
+/// `<stem>/` directory holding `up.sql`, `down.sql`, an optional `meta.toml` and
+/// any number of ordered `up.<part>.sql` / `down.<part>.sql` files.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub revision: String,
+    pub down_revisions: Vec<String>,
+    /// Revisions, possibly of other modules, that must be applied first (`-- Depends On`).
+    pub depends_on: Vec<String>,
+    /// Revisions this migration was squashed from (`-- Replaces`).
+    pub replaces: Vec<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub flags: Vec<String>,
+    pub path: PathBuf,
+    pub up_files: Vec<PathBuf>,
+    pub down_files: Vec<PathBuf>,
+    pub(crate) sort_key: String,
+    pub(crate) order: usize,
+}
+
+impl Migration {
+    pub fn is_directory(&self) -> bool {
+        self.path.is_dir()
+    }
+
+    /// First parent revision, `None` for a base migration.
+    pub fn down_revision(&self) -> Option<&str> {
+        self.down_revisions.first().map(|s| s.as_str())
+    }
+
+    pub fn up_sql(&self) -> io::Result<String> {
+        read_files(&self.up_files)
+    }
+
+    pub fn down_sql(&self) -> io::Result<String> {
+        read_files(&self.down_files)
+    }
+
+    /// SHA-256 over every up and down file of the migration, in execution order, followed by its
+    /// `meta.toml` if it has one.
+    pub fn checksum(&self) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        let meta_file = self.path.join("meta.toml");
+        let meta_file = (self.is_directory() && meta_file.is_file()).then_some(meta_file);
+        for file in self.up_files.iter().chain(self.down_files.iter()).chain(meta_file.iter()) {
+            let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+            hasher.update(file_name.as_bytes());
+            hasher.update([0]);
+            hasher.update(fs::read(file)?);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Points the migration at new parents, in its `meta.toml` if it lists them there and in its
+    /// `-- Down Revision` headers otherwise.
+    pub fn set_down_revisions(&self, revisions: &[String]) -> io::Result<()> {
+        self.set_revisions("Down Revision", "down_revisions", revisions)
+    }
+
+    /// Replaces the `-- Depends On` revisions, see `set_down_revisions`.
+    pub fn set_depends_on(&self, revisions: &[String]) -> io::Result<()> {
+        self.set_revisions("Depends On", "depends_on", revisions)
+    }
+
+    fn set_revisions(&self, key: &str, meta_key: &str, revisions: &[String]) -> io::Result<()> {
+        let meta_file = self.path.join("meta.toml");
+        if self.is_directory() && meta_file.is_file() {
+            let mut meta: toml::Table = toml::from_str(&fs::read_to_string(&meta_file)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", meta_file.display(), e)))?;
+            if meta.contains_key(meta_key) {
+                let revisions = revisions.iter().map(|revision| toml::Value::String(revision.clone())).collect();
+                meta.insert(meta_key.to_string(), toml::Value::Array(revisions));
+                let meta = toml::to_string(&meta).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                return fs::write(&meta_file, meta);
+            }
+        }
+        let value = if revisions.is_empty() { "None".to_string() } else { revisions.join(", ") };
+        let mut found = false;
+        for file in self.up_files.iter().chain(self.down_files.iter()) {
+            found |= rewrite_header(file, key, &value)?;
+        }
+        match self.up_files.first() {
+            Some(up_file) if !found => insert_header(up_file, key, &value),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A migration in the `repeatable/` directory, re-applied whenever its checksum changes.
+#[derive(Debug, Clone)]
+pub struct RepeatableMigration {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl RepeatableMigration {
+    pub fn sql(&self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+}
+
+/// Loads the `*.sql` files of `<dir>/repeatable`, sorted by file name.
+pub fn load_repeatable_migrations(dir: &Path) -> io::Result<Vec<RepeatableMigration>> {
+    let repeatable_dir = dir.join("repeatable");
+    if !repeatable_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut migrations = Vec::new();
+    for entry in fs::read_dir(&repeatable_dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_file() && file_name.ends_with(".sql") {
+            migrations.push(RepeatableMigration { name: file_name, path });
+        }
+    }
+    migrations.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(migrations)
+}
+
+/// SHA-256 of a piece of SQL.
+pub fn sql_checksum(sql: &str) -> String {
+    format!("{:x}", Sha256::digest(sql.as_bytes()))
+}
+
+/// Loads every migration in `dir`, ordered so that each migration comes after its down revisions.
+pub fn load_migrations(dir: &Path) -> io::Result<Vec<Migration>> {
+    let schemes = stem_schemes();
+    let mut migrations = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_dir() {
+            if path.join("up.sql").is_file() {
+                migrations.push(load_directory(&path, &file_name, &schemes)?);
+            }
+        } else if let Some(stem) = file_name.strip_suffix("_up.sql") {
+            migrations.push(load_file_pair(&path, stem, &schemes)?);
+        }
+    }
+    Ok(order_migrations(migrations))
+}
+
+/// Orders migrations parents first, breaking ties between independent migrations by file name.
+fn order_migrations(mut remaining: Vec<Migration>) -> Vec<Migration> {
+    remaining.sort_by(|a, b| a.sort_key.cmp(&b.sort_key));
+    let mut ordered: Vec<Migration> = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|migration| {
+            migration.down_revisions.iter().chain(migration.depends_on.iter())
+                .all(|parent| !remaining.iter().any(|m| &m.revision == parent))
+        });
+        // A cycle in the down revisions leaves nothing ready; fall back to file name order.
+        let mut migration = remaining.remove(ready.unwrap_or(0));
+        migration.order = ordered.len();
+        ordered.push(migration);
+    }
+    ordered
+}
+
+fn load_file_pair(up_file: &Path, stem: &str, schemes: &[NamingScheme]) -> io::Result<Migration> {
+    let down_file = up_file.with_file_name(format!("{}_down.sql", stem));
+    let (revision, name) = parse_stem(stem, schemes);
+    let headers = read_headers(up_file)?;
+
+    Ok(Migration {
+        revision: header(&headers, "Revision").unwrap_or(revision),
+        down_revisions: parse_down_revisions(header(&headers, "Down Revision")),
+        depends_on: parse_down_revisions(header(&headers, "Depends On")),
+        replaces: parse_down_revisions(header(&headers, "Replaces")),
+        name: header(&headers, "Name").unwrap_or(name),
+        description: None,
+        flags: Vec::new(),
+        path: up_file.to_path_buf(),
+        up_files: vec![up_file.to_path_buf()],
+        down_files: if down_file.is_file() { vec![down_file] } else { Vec::new() },
+        sort_key: stem.to_string(),
+        order: 0,
+    })
+}
+
+fn load_directory(dir: &Path, stem: &str, schemes: &[NamingScheme]) -> io::Result<Migration> {
+    let (revision, name) = parse_stem(stem, schemes);
+    let headers = read_headers(&dir.join("up.sql"))?;
+
+    let meta_file = dir.join("meta.toml");
+    let meta: Meta = if meta_file.is_file() {
+        toml::from_str(&fs::read_to_string(&meta_file)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", meta_file.display(), e)))?
+    } else {
+        Meta::default()
+    };
+
+    let down_revisions = match meta.down_revisions {
+        Some(down_revisions) => down_revisions,
+        None => parse_down_revisions(header(&headers, "Down Revision")),
+    };
+
+    let depends_on = match meta.depends_on {
+        Some(depends_on) => depends_on,
+        None => parse_down_revisions(header(&headers, "Depends On")),
+    };
+
+    let replaces = match meta.replaces {
+        Some(replaces) => replaces,
+        None => parse_down_revisions(header(&headers, "Replaces")),
+    };
+
+    Ok(Migration {
+        revision: meta.revision.or_else(|| header(&headers, "Revision")).unwrap_or(revision),
+        down_revisions,
+        depends_on,
+        replaces,
+        name: header(&headers, "Name").unwrap_or(name),
+        description: meta.description,
+        flags: meta.flags,
+        path: dir.to_path_buf(),
+        up_files: ordered_files(dir, "up")?,
+        down_files: ordered_files(dir, "down")?,
+        sort_key: stem.to_string(),
+        order: 0,
+    })
+}
+
+/// `<prefix>.sql` followed by the `<prefix>.<part>.sql` files sorted by name.
+pub(crate) fn ordered_files(dir: &Path, prefix: &str) -> io::Result<Vec<PathBuf>> {
+    let main = dir.join(format!("{}.sql", prefix));
+    let mut parts: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_file() && file_name.starts_with(&format!("{}.", prefix)) && file_name.ends_with(".sql") && path != main {
+            parts.push(path);
+        }
+    }
+    parts.sort();
+
+    let mut files = Vec::new();
+    if main.is_file() {
+        files.push(main);
+    }
+    files.extend(parts);
+    Ok(files)
+}
+
+fn read_files(files: &[PathBuf]) -> io::Result<String> {
+    let mut sql = String::new();
+    for file in files {
+        sql.push_str(&fs::read_to_string(file)?);
+        if !sql.ends_with('\n') {
+            sql.push('\n');
+        }
+    }
+    Ok(sql)
+}
+
+/// The naming schemes stems are parsed with: the configured one, then the presets.
+fn stem_schemes() -> Vec<NamingScheme> {
+    let configured = NamingScheme::from_env();
+    [configured.template(), "legacy", "timestamp", "sequential", "hash"].into_iter()
+        .map(NamingScheme::new)
+        .collect()
+}
+
+/// Splits a stem into the revision and a readable name, for files without `-- Revision` or
+/// `-- Name` headers, trying each of `schemes` in turn. A stem none of them generated is its own
+/// revision.
+fn parse_stem(stem: &str, schemes: &[NamingScheme]) -> (String, String) {
+    let parsed = schemes.iter().find_map(|scheme| scheme.parse(stem));
+    parsed.unwrap_or_else(|| (stem.to_string(), stem.to_string()))
+}
+
+/// Reads the leading `-- Key: value` comment lines of a migration file.
+pub fn read_headers(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let reader = io::BufReader::new(fs::File::open(path)?);
+    let mut headers = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let Some(comment) = line.trim().strip_prefix("--") else {
+            break;
+        };
+        if let Some((key, value)) = comment.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    Ok(headers)
+}
+
+/// Replaces the value of the `-- <key>:` header of a migration file, returning whether it had one.
+pub fn rewrite_header(path: &Path, key: &str, value: &str) -> io::Result<bool> {
+    let content = fs::read_to_string(path)?;
+    let mut rewritten = String::with_capacity(content.len());
+    let mut in_headers = true;
+    let mut found = false;
+    for line in content.split_inclusive('\n') {
+        if in_headers {
+            match line.trim().strip_prefix("--") {
+                Some(comment) if comment.split_once(':').is_some_and(|(k, _)| k.trim() == key) => {
+                    rewritten.push_str(&format!("-- {}: {}\n", key, value));
+                    found = true;
+                    continue;
+                }
+                Some(_) => {}
+                None => in_headers = false,
+            }
+        }
+        rewritten.push_str(line);
+    }
+    if found {
+        fs::write(path, rewritten)?;
+    }
+    Ok(found)
+}
+
+/// Adds a `-- <key>: <value>` header after the leading comments of a migration file.
+pub fn insert_header(path: &Path, key: &str, value: &str) -> io::Result<()> {
+    let content = fs::read_to_string(path)?;
+    let end: usize = content.split_inclusive('\n')
+        .take_while(|line| line.trim().starts_with("--"))
+        .map(|line| line.len())
+        .sum();
+    let separator = if end > 0 && !content[..end].ends_with('\n') { "\n" } else { "" };
+    let header = format!("{}-- {}: {}\n", separator, key, value);
+    fs::write(path, format!("{}{}{}", &content[..end], header, &content[end..]))
+}
+
+/// Writes the up and down files of a new migration in `dir`, named by `naming`, with the given
+/// headers after its revision, and returns its file stem.
+pub fn write_new_migration(dir: &Path, naming: &NamingScheme, name: &str, headers: &[(&str, String)], up_sql: &str, down_sql: &str) -> io::Result<String> {
+    fs::create_dir_all(dir)?;
+    let migrations = load_migrations(dir)?;
+    let migration_name = naming.generate(name, &migrations);
+    write_migration(dir, &migration_name, name, headers, up_sql, down_sql)?;
+    Ok(migration_name.stem)
+}
+
+/// Writes the up and down files of a migration whose revision and file stem are already chosen.
+pub fn write_migration(dir: &Path, migration_name: &MigrationName, name: &str, headers: &[(&str, String)], up_sql: &str, down_sql: &str) -> io::Result<()> {
+    let up_file = dir.join(format!("{}_up.sql", migration_name.stem));
+    let down_file = dir.join(format!("{}_down.sql", migration_name.stem));
+
+    for (path, comment, sql) in [(&up_file, "upgrade", up_sql), (&down_file, "downgrade", down_sql)] {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)?;
+        writeln!(file, "-- SQL commands to {}", comment)?;
+        writeln!(file, "-- Revision: {}", migration_name.revision)?;
+        for (key, value) in headers {
+            writeln!(file, "-- {}: {}", key, value)?;
+        }
+        writeln!(file, "-- Name: {}", name)?;
+        if !sql.is_empty() {
+            write!(file, "\n{}", sql)?;
+        }
+    }
+    Ok(())
+}
+
+/// `sql` without its leading header comments and the blank lines after them.
+pub fn strip_headers(sql: &str) -> &str {
+    let mut rest = sql;
+    while rest.trim_start_matches([' ', '\t']).starts_with("--") {
+        rest = rest.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+    rest.trim_start_matches(['\n', '\r'])
+}
+
+fn header(headers: &[(String, String)], key: &str) -> Option<String> {
+    headers.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+}
+
+fn parse_down_revisions(value: Option<String>) -> Vec<String> {
+    match value.as_deref() {
+        None | Some("None") | Some("") => Vec::new(),
+        Some(value) => value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::TempDir;
+
+    #[test]
+    fn test_load_file_pairs() {
+        let migrations = load_migrations(Path::new("migrations")).unwrap();
+        assert_eq!(migrations.len(), 2);
+        assert_eq!(migrations[0].revision, "f44e620f-60e0-4470-8904-44b4022b11a5");
+        assert_eq!(migrations[0].down_revision(), None);
+        assert_eq!(migrations[1].revision, "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd");
+        assert_eq!(migrations[1].down_revision(), Some("f44e620f-60e0-4470-8904-44b4022b11a5"));
+        assert_eq!(migrations[1].down_files.len(), 1);
+    }
+
+    #[test]
+    fn test_load_directory_migration() {
+        let dir = TempDir::new();
+        let migration_dir = dir.join("2024-07-03_09:00:00_5b0e7d4c-2f1a-4c1e-9b5e-0c7f0d3e2a11_add_catalog");
+        fs::create_dir(&migration_dir).unwrap();
+        fs::write(migration_dir.join("up.sql"), "CREATE SCHEMA catalog;\n").unwrap();
+        fs::write(migration_dir.join("up.02-seed.sql"), "INSERT INTO catalog.items VALUES (1);\n").unwrap();
+        fs::write(migration_dir.join("up.01-tables.sql"), "CREATE TABLE catalog.items (id INT);\n").unwrap();
+        fs::write(migration_dir.join("down.sql"), "DROP SCHEMA catalog CASCADE;\n").unwrap();
+        fs::write(
+            migration_dir.join("meta.toml"),
+            "revision = \"catalog\"\ndown_revisions = [\"622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd\"]\n\
+            description = \"Catalog schema with seed data\"\nflags = [\"seed\"]\n",
+        ).unwrap();
+
+        let migrations = load_migrations(&dir).unwrap();
+        assert_eq!(migrations.len(), 1);
+        let migration = &migrations[0];
+        assert!(migration.is_directory());
+        assert_eq!(migration.revision, "catalog");
+        assert_eq!(migration.down_revision(), Some("622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd"));
+        assert_eq!(migration.name, "add catalog");
+        assert_eq!(migration.description.as_deref(), Some("Catalog schema with seed data"));
+        assert_eq!(migration.flags, vec!["seed"]);
+        assert_eq!(
+            migration.up_sql().unwrap(),
+            "CREATE SCHEMA catalog;\nCREATE TABLE catalog.items (id INT);\nINSERT INTO catalog.items VALUES (1);\n"
+        );
+        assert_eq!(migration.down_sql().unwrap(), "DROP SCHEMA catalog CASCADE;\n");
+
+        let checksum = migration.checksum().unwrap();
+        fs::write(migration_dir.join("up.02-seed.sql"), "INSERT INTO catalog.items VALUES (2);\n").unwrap();
+        let checksum_after_up = load_migrations(&dir).unwrap()[0].checksum().unwrap();
+        assert_ne!(checksum_after_up, checksum);
+        let meta = fs::read_to_string(migration_dir.join("meta.toml")).unwrap();
+        fs::write(migration_dir.join("meta.toml"), meta.replace("[\"seed\"]", "[\"seed\", \"slow\"]")).unwrap();
+        assert_ne!(load_migrations(&dir).unwrap()[0].checksum().unwrap(), checksum_after_up);
+    }
+
+    #[test]
+    fn test_identity_from_headers() {
+        let dir = TempDir::new();
+        fs::write(dir.join("a1b2c3d4_add_order_items_up.sql"), "-- Revision: a1b2c3d4\n-- Down Revision: 0f9e8d7c\n-- Name: add_order_items\n").unwrap();
+        fs::write(dir.join("a1b2c3d4_add_order_items_down.sql"), "-- Revision: a1b2c3d4\n").unwrap();
+        fs::write(dir.join("0f9e8d7c_add_orders_up.sql"), "-- Revision: 0f9e8d7c\n-- Down Revision: None\n-- Name: add_orders\n").unwrap();
+        fs::write(dir.join("0f9e8d7c_add_orders_down.sql"), "-- Revision: 0f9e8d7c\n").unwrap();
+
+        let migrations = load_migrations(&dir).unwrap();
+        assert_eq!(migrations[0].revision, "0f9e8d7c");
+        assert_eq!(migrations[0].name, "add_orders");
+        assert_eq!(migrations[1].revision, "a1b2c3d4");
+        assert_eq!(migrations[1].name, "add_order_items");
+    }
+
+    #[test]
+    fn test_identity_from_stem() {
+        let dir = TempDir::new();
+        for stem in ["V0001_add_users", "20240703090507_0f9e8d7c_add_orders", "notes"] {
+            fs::write(dir.join(format!("{}_up.sql", stem)), "SELECT 1;\n").unwrap();
+        }
+
+        let mut migrations = load_migrations(&dir).unwrap();
+        migrations.sort_by(|a, b| a.revision.cmp(&b.revision));
+
+        let identities: Vec<(&str, &str)> = migrations.iter().map(|m| (m.revision.as_str(), m.name.as_str())).collect();
+        assert_eq!(identities, vec![("0f9e8d7c", "add orders"), ("V0001", "add users"), ("notes", "notes")]);
+    }
+
+    #[test]
+    fn test_rewrite_headers() {
+        let dir = TempDir::new();
+        fs::write(dir.join("b_wallets_up.sql"), "-- Revision: b\n-- Down Revision: a\n-- Depends On: x, y\n\nCREATE TABLE wallets (id INT);\n").unwrap();
+        fs::write(dir.join("b_wallets_down.sql"), "-- Revision: b\n-- Down Revision: a\n\nDROP TABLE wallets;\n").unwrap();
+        let catalog = dir.join("c_catalog");
+        fs::create_dir(&catalog).unwrap();
+        fs::write(catalog.join("up.sql"), "-- Down Revision: ignored\nCREATE SCHEMA catalog;\n").unwrap();
+        fs::write(catalog.join("meta.toml"), "revision = \"c\"\ndown_revisions = [\"b\"]\n").unwrap();
+
+        let migrations = load_migrations(&dir).unwrap();
+        migrations[0].set_down_revisions(&["s".to_string()]).unwrap();
+        migrations[0].set_depends_on(&[]).unwrap();
+        migrations[1].set_down_revisions(&["s".to_string(), "t".to_string()]).unwrap();
+
+        let migrations = load_migrations(&dir).unwrap();
+        assert_eq!(migrations[0].down_revisions, vec!["s"]);
+        assert!(migrations[0].depends_on.is_empty());
+        assert_eq!(migrations[1].down_revisions, vec!["s", "t"]);
+        assert_eq!(migrations[0].down_sql().unwrap(), "-- Revision: b\n-- Down Revision: s\n\nDROP TABLE wallets;\n");
+        assert_eq!(fs::read_to_string(catalog.join("up.sql")).unwrap(), "-- Down Revision: ignored\nCREATE SCHEMA catalog;\n");
+        assert_eq!(strip_headers(&migrations[0].up_sql().unwrap()), "CREATE TABLE wallets (id INT);\n");
+    }
+
+    #[test]
+    fn test_depends_on_ordering() {
+        let dir = TempDir::new();
+        fs::write(dir.join("a_users_up.sql"), "-- Revision: a\n").unwrap();
+        fs::write(dir.join("b_wallets_up.sql"), "-- Revision: b\n-- Depends On: c, users_module_rev\n").unwrap();
+        fs::write(dir.join("c_accounts_up.sql"), "-- Revision: c\n").unwrap();
+
+        let migrations = load_migrations(&dir).unwrap();
+        let revisions: Vec<&str> = migrations.iter().map(|m| m.revision.as_str()).collect();
+        assert_eq!(revisions, vec!["a", "c", "b"]);
+        assert_eq!(migrations[2].depends_on, vec!["c", "users_module_rev"]);
+    }
+}
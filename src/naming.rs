@@ -0,0 +1,217 @@
+use std::env;
+use chrono::{DateTime, Utc};
+use dotenv::dotenv;
+use uuid::Uuid;
+use crate::migration::Migration;
+
+/// Template used to name new migration files.
+///
+/// Supported placeholders:
+/// - `{datetime}`: UTC time as `2024-07-01_13:01:16`
+/// - `{timestamp}`: UTC time as `20240701130116`
+/// - `{seq}`: next sequential version, `V0001`, `V0002`, ...
+/// - `{uuid}`: a random UUID
+/// - `{hash}`: a random 8 character hex id
+/// - `{name}`: the migration name
+///
+/// The revision id written to the file headers is the `{uuid}`, `{hash}` or `{seq}` value, in that
+/// order of preference; a short hash is generated when the template contains none of them. The
+/// name is written with everything but letters, digits, `-` and `_` replaced by `_`, so it cannot
+/// leave the migration directory.
+#[derive(Debug, Clone)]
+pub struct NamingScheme {
+    template: String,
+}
+
+/// Revision id and file stem generated for a new migration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationName {
+    pub revision: String,
+    pub stem: String,
+}
+
+impl NamingScheme {
+    /// Creates a scheme from a template or one of the presets `legacy`, `timestamp`, `sequential`
+    /// and `hash`.
+    pub fn new(template: &str) -> Self {
+        let template = match template {
+            "legacy" => "{datetime}_{uuid}_{name}",
+            "timestamp" => "{timestamp}_{hash}_{name}",
+            "sequential" => "{seq}_{name}",
+            "hash" => "{hash}_{name}",
+            template => template,
+        };
+        NamingScheme { template: template.to_string() }
+    }
+
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        NamingScheme::new(&env::var("MIGRATION_NAMING").unwrap_or_else(|_| "legacy".to_string()))
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn generate(&self, name: &str, existing: &[Migration]) -> MigrationName {
+        self.generate_at(name, existing, Utc::now())
+    }
+
+    fn generate_at(&self, name: &str, existing: &[Migration], now: DateTime<Utc>) -> MigrationName {
+        let uuid = Uuid::new_v4();
+        let hash = uuid.simple().to_string()[..8].to_string();
+        let seq = format!("V{:04}", next_sequence(existing));
+
+        let revision = if self.template.contains("{uuid}") {
+            uuid.to_string()
+        } else if self.template.contains("{hash}") {
+            hash.clone()
+        } else if self.template.contains("{seq}") {
+            seq.clone()
+        } else {
+            hash.clone()
+        };
+
+        let stem = self.template
+            .replace("{datetime}", &now.format("%Y-%m-%d_%H:%M:%S").to_string())
+            .replace("{timestamp}", &now.format("%Y%m%d%H%M%S").to_string())
+            .replace("{seq}", &seq)
+            .replace("{uuid}", &uuid.to_string())
+            .replace("{hash}", &hash)
+            .replace("{name}", &file_name(name));
+
+        MigrationName { revision, stem }
+    }
+
+    /// Splits a file stem generated by this scheme into its revision and a readable name, the
+    /// inverse of `generate`. `None` when the stem does not match the template or the template has
+    /// no revision placeholder.
+    pub fn parse(&self, stem: &str) -> Option<(String, String)> {
+        let mut values = Vec::new();
+        if !match_template(&self.template, stem, &mut values) {
+            return None;
+        }
+        let value = |placeholder: &str| values.iter().find(|(p, _)| *p == placeholder).map(|(_, v)| v.to_string());
+        let revision = value("{uuid}").or_else(|| value("{hash}")).or_else(|| value("{seq}"))?;
+        let name = value("{name}").map_or_else(|| revision.clone(), |name| name.replace('_', " "));
+        Some((revision, name))
+    }
+}
+
+/// `name` usable in a file name, with spaces, path separators and other characters replaced by
+/// underscores.
+pub fn file_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect()
+}
+
+const PLACEHOLDERS: [&str; 6] = ["{datetime}", "{timestamp}", "{seq}", "{uuid}", "{hash}", "{name}"];
+
+/// Matches `stem` against `template`, collecting the value of each placeholder.
+fn match_template<'a>(template: &str, stem: &'a str, values: &mut Vec<(&'static str, &'a str)>) -> bool {
+    let Some(start) = template.find('{').filter(|&i| PLACEHOLDERS.iter().any(|p| template[i..].starts_with(p))) else {
+        return template == stem;
+    };
+    let Some(rest) = stem.strip_prefix(&template[..start]) else {
+        return false;
+    };
+    let placeholder = PLACEHOLDERS.iter().find(|p| template[start..].starts_with(*p)).unwrap();
+    let template = &template[start + placeholder.len()..];
+
+    // Try the longest value first so that `{name}` takes as much as the rest of the template allows.
+    for end in (1..=rest.len()).rev().filter(|&end| rest.is_char_boundary(end)) {
+        let value = &rest[..end];
+        if !matches_placeholder(placeholder, value) {
+            continue;
+        }
+        values.push((placeholder, value));
+        if match_template(template, &rest[end..], values) {
+            return true;
+        }
+        values.pop();
+    }
+    false
+}
+
+fn matches_placeholder(placeholder: &str, value: &str) -> bool {
+    let shape = |pattern: &str| {
+        value.len() == pattern.len() && value.chars().zip(pattern.chars()).all(|(c, p)| match p {
+            'd' => c.is_ascii_digit(),
+            'x' => c.is_ascii_hexdigit(),
+            p => c == p,
+        })
+    };
+    match placeholder {
+        "{datetime}" => shape("dddd-dd-dd_dd:dd:dd"),
+        "{timestamp}" => shape("dddddddddddddd"),
+        "{seq}" => value.len() > 1 && value.starts_with('V') && value[1..].chars().all(|c| c.is_ascii_digit()),
+        "{uuid}" => shape("xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx"),
+        "{hash}" => shape("xxxxxxxx"),
+        _ => !value.is_empty(),
+    }
+}
+
+/// One past the highest `V<number>` revision among `existing`.
+fn next_sequence(existing: &[Migration]) -> u32 {
+    existing.iter()
+        .filter_map(|migration| {
+            let digits = migration.revision.strip_prefix('V')?;
+            digits.parse::<u32>().ok()
+        })
+        .max()
+        .unwrap_or(0) + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::Path;
+    use crate::migration::load_migrations;
+
+    #[test]
+    fn test_presets() {
+        let now = Utc.with_ymd_and_hms(2024, 7, 3, 9, 5, 7).unwrap();
+
+        let name = NamingScheme::new("legacy").generate_at("add_orders", &[], now);
+        assert!(name.stem.starts_with("2024-07-03_09:05:07_"));
+        assert!(name.stem.ends_with(&format!("{}_add_orders", name.revision)));
+        assert_eq!(name.revision.len(), 36);
+
+        let name = NamingScheme::new("timestamp").generate_at("add_orders", &[], now);
+        assert_eq!(name.stem, format!("20240703090507_{}_add_orders", name.revision));
+        assert_eq!(name.revision.len(), 8);
+
+        let name = NamingScheme::new("hash").generate_at("add_orders", &[], now);
+        assert_eq!(name.stem, format!("{}_add_orders", name.revision));
+
+        let name = NamingScheme::new("sequential").generate_at("add_orders", &[], now);
+        assert_eq!(name, MigrationName { revision: "V0001".to_string(), stem: "V0001_add_orders".to_string() });
+    }
+
+    #[test]
+    fn test_sequential_continues_existing() {
+        let mut existing = load_migrations(Path::new("migrations")).unwrap();
+        existing[1].revision = "V0007".to_string();
+        let name = NamingScheme::new("{seq}-{name}").generate("add_orders", &existing);
+        assert_eq!(name.stem, "V0008-add_orders");
+    }
+
+    #[test]
+    fn test_parse() {
+        let now = Utc.with_ymd_and_hms(2024, 7, 3, 9, 5, 7).unwrap();
+        for preset in ["legacy", "timestamp", "sequential", "hash", "{name}-{hash}"] {
+            let scheme = NamingScheme::new(preset);
+            let name = scheme.generate_at("add_order_items", &[], now);
+            assert_eq!(scheme.parse(&name.stem), Some((name.revision, "add order items".to_string())), "{}", preset);
+        }
+        assert_eq!(NamingScheme::new("sequential").parse("V0001_add_users"), Some(("V0001".to_string(), "add users".to_string())));
+        assert_eq!(NamingScheme::new("legacy").parse("V0001_add_users"), None);
+        assert_eq!(NamingScheme::new("{datetime}_{name}").parse("2024-07-03_09:05:07_add_users"), None);
+    }
+
+    #[test]
+    fn test_name_cannot_leave_directory() {
+        let name = NamingScheme::new("sequential").generate("../auth/add users", &[]);
+        assert_eq!(name.stem, "V0001____auth_add_users");
+    }
+}
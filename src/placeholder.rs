@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use crate::error::MigrateError;
+
+/// Replaces `${name}` placeholders in `sql` with their values from `vars`.
+///
+/// `$${name}` is written out as a literal `${name}`. Referencing a placeholder without a value is
+/// an error rather than leaving it in the SQL.
+pub fn expand(sql: &str, vars: &BTreeMap<String, String>) -> Result<String, MigrateError> {
+    let mut expanded = String::with_capacity(sql.len());
+    let mut rest = sql;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("$${") {
+            expanded.push('$');
+            rest = &rest[2..];
+            let end = rest.find('}').map_or(rest.len(), |end| end + 1);
+            expanded.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let name = rest.strip_prefix("${").and_then(|after| after.find('}').map(|end| &after[..end]));
+        match name {
+            Some(name) if is_placeholder_name(name) => {
+                let value = vars.get(name).ok_or_else(|| MigrateError::UndefinedPlaceholder(name.to_string()))?;
+                expanded.push_str(value);
+                rest = &rest[name.len() + 3..];
+            }
+            _ => {
+                expanded.push('$');
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("schema".to_string(), "tenant_1".to_string()),
+            ("app_role".to_string(), "app".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_expand() {
+        let sql = "CREATE TABLE ${schema}.users (id INT);\nGRANT SELECT ON ${schema}.users TO ${app_role};";
+        assert_eq!(
+            expand(sql, &vars()).unwrap(),
+            "CREATE TABLE tenant_1.users (id INT);\nGRANT SELECT ON tenant_1.users TO app;"
+        );
+    }
+
+    #[test]
+    fn test_escape_and_dollar_quotes() {
+        let sql = "CREATE FUNCTION f() RETURNS TEXT AS $$ SELECT '$${schema}' || $1 $$ LANGUAGE sql;";
+        assert_eq!(
+            expand(sql, &vars()).unwrap(),
+            "CREATE FUNCTION f() RETURNS TEXT AS $$ SELECT '${schema}' || $1 $$ LANGUAGE sql;"
+        );
+    }
+
+    #[test]
+    fn test_undefined_placeholder() {
+        let result = expand("CREATE TABLE t (id INT) TABLESPACE ${tablespace};", &vars());
+        assert!(matches!(result, Err(MigrateError::UndefinedPlaceholder(name)) if name == "tablespace"));
+    }
+}
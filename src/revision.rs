@@ -0,0 +1,135 @@
+use crate::error::MigrateError;
+use crate::migration::Migration;
+
+/// Resolves a revision reference to a revision, `None` meaning base (before the first migration).
+///
+/// Accepted references:
+/// - `base`, and `head` for the last migration;
+/// - `current`, read through `current` only when needed;
+/// - `head~N`, `head-N`, `current~N`, `current-N` and `current+N`, counted N migrations back or
+///   forward in upgrade order;
+/// - anything else as in [`find`].
+pub fn resolve<F>(migrations: &[Migration], reference: &str, current: F) -> Result<Option<String>, MigrateError>
+where
+    F: FnOnce() -> Result<Option<String>, MigrateError>,
+{
+    let mut ordered: Vec<&Migration> = migrations.iter().collect();
+    ordered.sort_by_key(|migration| migration.order);
+    let unknown = || MigrateError::UnknownRevision(reference.to_string());
+
+    if reference == "base" {
+        return Ok(None);
+    }
+    let (from_head, offset) = if let Some(offset) = reference.strip_prefix("head") {
+        (true, offset)
+    } else if let Some(offset) = reference.strip_prefix("current") {
+        (false, offset)
+    } else {
+        return find(migrations, reference).map(|migration| Some(migration.revision.clone()));
+    };
+    // Names such as `head-office` are not offsets, so they are looked up like any other name.
+    let Some(steps) = parse_offset(offset) else {
+        return find(migrations, reference).map(|migration| Some(migration.revision.clone()));
+    };
+    let start = if from_head {
+        ordered.len() as i64 - 1
+    } else {
+        match current()? {
+            Some(current) => ordered.iter().position(|m| m.revision == current)
+                .ok_or(MigrateError::UnknownRevision(current))? as i64,
+            None => -1,
+        }
+    };
+    match start + steps {
+        -1 => Ok(None),
+        index if index < -1 || index >= ordered.len() as i64 => Err(unknown()),
+        index => Ok(Some(ordered[index as usize].revision.clone())),
+    }
+}
+
+/// The steps of an offset after `head` or `current`: nothing, `~N`, `-N` or `+N`.
+fn parse_offset(offset: &str) -> Option<i64> {
+    let mut chars = offset.chars();
+    let sign = match chars.next() {
+        None => return Some(0),
+        Some('~' | '-') => -1,
+        Some('+') => 1,
+        Some(_) => return None,
+    };
+    chars.as_str().parse::<i64>().ok().map(|steps| sign * steps)
+}
+
+/// Finds the migration `reference` names: a full revision, a migration name, with underscores
+/// or spaces as in the file name or the name, or a revision prefix shared by no other migration.
+pub fn find<'a>(migrations: &'a [Migration], reference: &str) -> Result<&'a Migration, MigrateError> {
+    if let Some(migration) = migrations.iter().find(|m| m.revision == reference) {
+        return Ok(migration);
+    }
+    let name = reference.replace('_', " ");
+    let named: Vec<&Migration> = migrations.iter().filter(|m| m.name == reference || m.name == name).collect();
+    let matches = if named.is_empty() {
+        migrations.iter().filter(|m| m.revision.starts_with(reference)).collect()
+    } else {
+        named
+    };
+    match matches.as_slice() {
+        [migration] => Ok(migration),
+        [] => Err(MigrateError::UnknownRevision(reference.to_string())),
+        _ => Err(MigrateError::AmbiguousRevision {
+            reference: reference.to_string(),
+            matches: matches.iter().map(|m| m.revision.clone()).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::migration::load_migrations;
+
+    const USERS: &str = "f44e620f-60e0-4470-8904-44b4022b11a5";
+    const WALLET: &str = "622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd";
+
+    #[test]
+    fn test_find() {
+        let migrations = load_migrations(Path::new("migrations")).unwrap();
+        assert_eq!(find(&migrations, USERS).unwrap().name, "add users");
+        assert_eq!(find(&migrations, "6225").unwrap().revision, WALLET);
+        assert_eq!(find(&migrations, "add wallet").unwrap().revision, WALLET);
+        assert_eq!(find(&migrations, "add_wallet").unwrap().revision, WALLET);
+        assert!(matches!(find(&migrations, "f44e620f-60e0-4470-8904-44b4022b11a6"), Err(MigrateError::UnknownRevision(_))));
+        // Substrings of the file name no longer match.
+        assert!(matches!(find(&migrations, "2024-07"), Err(MigrateError::UnknownRevision(_))));
+        assert!(matches!(find(&migrations, ""), Err(MigrateError::AmbiguousRevision { matches, .. }) if matches.len() == 2));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut migrations = load_migrations(Path::new("migrations")).unwrap();
+        migrations.reverse();
+        let at_users = || Ok(Some(USERS.to_string()));
+        let at_base = || Ok(None);
+        let offline = || Err(MigrateError::UnknownRevision("current".to_string()));
+
+        assert_eq!(resolve(&migrations, "head", offline).unwrap().as_deref(), Some(WALLET));
+        assert_eq!(resolve(&migrations, "head~1", offline).unwrap().as_deref(), Some(USERS));
+        assert_eq!(resolve(&migrations, "head~2", offline).unwrap(), None);
+        assert_eq!(resolve(&migrations, "base", offline).unwrap(), None);
+        assert_eq!(resolve(&migrations, "f44e", offline).unwrap().as_deref(), Some(USERS));
+        assert_eq!(resolve(&migrations, "current", at_users).unwrap().as_deref(), Some(USERS));
+        assert_eq!(resolve(&migrations, "current+1", at_users).unwrap().as_deref(), Some(WALLET));
+        assert_eq!(resolve(&migrations, "current-1", at_users).unwrap(), None);
+        assert_eq!(resolve(&migrations, "current+1", at_base).unwrap().as_deref(), Some(USERS));
+        assert!(matches!(resolve(&migrations, "head~3", offline), Err(MigrateError::UnknownRevision(r)) if r == "head~3"));
+        assert!(matches!(resolve(&migrations, "current+2", at_users), Err(MigrateError::UnknownRevision(_))));
+        assert!(matches!(resolve(&migrations, "current", offline), Err(MigrateError::UnknownRevision(r)) if r == "current"));
+        assert!(matches!(resolve(&migrations, "head~x", offline), Err(MigrateError::UnknownRevision(_))));
+        assert!(matches!(resolve(&migrations, "current-office", offline), Err(MigrateError::UnknownRevision(r)) if r == "current-office"));
+        for migration in &mut migrations {
+            migration.name = if migration.revision == USERS { "head-office" } else { "current-accounts" }.to_string();
+        }
+        assert_eq!(resolve(&migrations, "head-office", offline).unwrap().as_deref(), Some(USERS));
+        assert_eq!(resolve(&migrations, "current-accounts", offline).unwrap().as_deref(), Some(WALLET));
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, BTreeSet};
+use postgres::{Client, Error};
+
+/// Tables pg_migrate keeps its own bookkeeping in, in the first schema of the search path, left out
+/// of schema snapshots. Tables with the same names in other schemas belong to the user.
+pub const BOOKKEEPING_TABLES: [&str; 4] = ["migrations", "history", "repeatable_migrations", "seeds"];
+
+const USER_SCHEMAS: &str = "n.nspname NOT IN ('pg_catalog', 'information_schema') \
+    AND n.nspname NOT LIKE 'pg_toast%' AND n.nspname NOT LIKE 'pg_temp%'";
+
+/// Structure of a database, as read from `pg_catalog`.
+///
+/// Every object is keyed by its schema-qualified name, so two snapshots can be compared
+/// object by object and rendered in a stable order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    /// Schemas other than `public`.
+    pub schemas: BTreeSet<String>,
+    pub tables: BTreeMap<String, Table>,
+    pub indexes: BTreeMap<String, Index>,
+    pub sequences: BTreeMap<String, Sequence>,
+    pub views: BTreeMap<String, View>,
+    pub functions: BTreeMap<String, Function>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub constraints: BTreeMap<String, Constraint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub data_type: String,
+    pub not_null: bool,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub name: String,
+    pub definition: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub definition: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    pub name: String,
+    pub data_type: String,
+    pub start: i64,
+    pub increment: i64,
+    pub min_value: i64,
+    pub max_value: i64,
+    pub cycle: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    pub name: String,
+    pub materialized: bool,
+    pub definition: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub signature: String,
+    pub definition: String,
+}
+
+impl Column {
+    pub fn to_sql(&self) -> String {
+        let mut sql = format!("{} {}", self.name, self.data_type);
+        if let Some(default) = &self.default {
+            sql.push_str(&format!(" DEFAULT {}", default));
+        }
+        if self.not_null {
+            sql.push_str(" NOT NULL");
+        }
+        sql
+    }
+}
+
+impl Constraint {
+    pub fn is_foreign_key(&self) -> bool {
+        self.definition.starts_with("FOREIGN KEY")
+    }
+}
+
+impl Sequence {
+    pub fn to_sql(&self) -> String {
+        format!(
+            "CREATE SEQUENCE {} AS {} START WITH {} INCREMENT BY {} MINVALUE {} MAXVALUE {}{};",
+            self.name, self.data_type, self.start, self.increment, self.min_value, self.max_value,
+            if self.cycle { " CYCLE" } else { "" }
+        )
+    }
+}
+
+impl View {
+    pub fn to_sql(&self) -> String {
+        let kind = if self.materialized { "MATERIALIZED VIEW" } else { "VIEW" };
+        format!("CREATE {} {} AS\n{}", kind, self.name, self.definition.trim_end())
+    }
+}
+
+impl Table {
+    /// `CREATE TABLE` with every constraint except foreign keys, which may reference tables
+    /// that do not exist yet; see `foreign_keys_sql`.
+    pub fn to_sql(&self) -> String {
+        let mut lines: Vec<String> = self.columns.iter().map(|column| format!("    {}", column.to_sql())).collect();
+        for constraint in self.constraints.values().filter(|constraint| !constraint.is_foreign_key()) {
+            lines.push(format!("    CONSTRAINT {} {}", constraint.name, constraint.definition));
+        }
+        format!("CREATE TABLE {} (\n{}\n);", self.name, lines.join(",\n"))
+    }
+
+    pub fn foreign_keys_sql(&self) -> Vec<String> {
+        self.constraints.values()
+            .filter(|constraint| constraint.is_foreign_key())
+            .map(|constraint| format!("ALTER TABLE {} ADD CONSTRAINT {} {};", self.name, constraint.name, constraint.definition))
+            .collect()
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+}
+
+impl Schema {
+    /// Reads every user-defined object outside the system schemas, except pg_migrate's own tables
+    /// in the current schema.
+    pub fn introspect(client: &mut Client) -> Result<Schema, Error> {
+        let mut schema = Schema::default();
+
+        let bookkeeping: Vec<String> = BOOKKEEPING_TABLES.iter().map(|t| t.to_string()).collect();
+
+        for row in client.query(
+            &format!(
+                "SELECT n.nspname FROM pg_namespace n \
+                WHERE {} AND n.nspname <> 'public' \
+                AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = n.oid AND e.deptype = 'e')",
+                USER_SCHEMAS
+            ),
+            &[],
+        )? {
+            schema.schemas.insert(row.get("nspname"));
+        }
+
+        for row in client.query(
+            &format!(
+                "SELECT n.nspname || '.' || c.relname AS name, a.attname, format_type(a.atttypid, a.atttypmod) AS data_type, \
+                a.attnotnull, pg_get_expr(d.adbin, d.adrelid) AS column_default \
+                FROM pg_class c \
+                JOIN pg_namespace n ON n.oid = c.relnamespace \
+                JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped \
+                LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum \
+                WHERE c.relkind IN ('r', 'p') AND {} AND NOT (n.nspname = current_schema() AND c.relname = ANY($1)) \
+                AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = c.oid AND e.deptype = 'e') \
+                ORDER BY name, a.attnum",
+                USER_SCHEMAS
+            ),
+            &[&bookkeeping],
+        )? {
+            let name: String = row.get("name");
+            let table = schema.tables.entry(name.clone()).or_insert_with(|| Table { name, ..Table::default() });
+            table.columns.push(Column {
+                name: row.get("attname"),
+                data_type: row.get("data_type"),
+                not_null: row.get("attnotnull"),
+                default: row.get("column_default"),
+            });
+        }
+
+        for row in client.query(
+            &format!(
+                "SELECT n.nspname || '.' || c.relname AS table_name, con.conname, pg_get_constraintdef(con.oid) AS definition \
+                FROM pg_constraint con \
+                JOIN pg_class c ON c.oid = con.conrelid \
+                JOIN pg_namespace n ON n.oid = c.relnamespace \
+                WHERE con.contype <> 'n' AND {}",
+                USER_SCHEMAS
+            ),
+            &[],
+        )? {
+            let table_name: String = row.get("table_name");
+            if let Some(table) = schema.tables.get_mut(&table_name) {
+                let name: String = row.get("conname");
+                table.constraints.insert(name.clone(), Constraint { name, definition: row.get("definition") });
+            }
+        }
+
+        for row in client.query(
+            &format!(
+                "SELECT n.nspname || '.' || ic.relname AS name, n.nspname || '.' || t.relname AS table_name, \
+                pg_get_indexdef(i.indexrelid) AS definition \
+                FROM pg_index i \
+                JOIN pg_class ic ON ic.oid = i.indexrelid \
+                JOIN pg_class t ON t.oid = i.indrelid \
+                JOIN pg_namespace n ON n.oid = ic.relnamespace \
+                WHERE {} AND NOT EXISTS (SELECT 1 FROM pg_constraint con WHERE con.conindid = i.indexrelid)",
+                USER_SCHEMAS
+            ),
+            &[],
+        )? {
+            let table_name: String = row.get("table_name");
+            if schema.tables.contains_key(&table_name) {
+                let name: String = row.get("name");
+                schema.indexes.insert(name.clone(), Index { name, table: table_name, definition: row.get("definition") });
+            }
+        }
+
+        for row in client.query(
+            "SELECT s.schemaname || '.' || s.sequencename AS name, s.data_type::TEXT AS data_type, s.start_value, \
+            s.increment_by, s.min_value, s.max_value, s.cycle \
+            FROM pg_sequences s \
+            JOIN pg_namespace n ON n.nspname = s.schemaname \
+            JOIN pg_class c ON c.relnamespace = n.oid AND c.relname = s.sequencename \
+            WHERE NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = c.oid AND e.deptype = 'e') \
+            AND NOT EXISTS ( \
+                SELECT 1 FROM pg_depend d \
+                JOIN pg_class t ON t.oid = d.refobjid \
+                JOIN pg_namespace tn ON tn.oid = t.relnamespace \
+                WHERE d.objid = c.oid AND d.deptype = 'a' AND tn.nspname = current_schema() AND t.relname = ANY($1) \
+            )",
+            &[&bookkeeping],
+        )? {
+            let name: String = row.get("name");
+            schema.sequences.insert(name.clone(), Sequence {
+                name,
+                data_type: row.get("data_type"),
+                start: row.get("start_value"),
+                increment: row.get("increment_by"),
+                min_value: row.get("min_value"),
+                max_value: row.get("max_value"),
+                cycle: row.get("cycle"),
+            });
+        }
+
+        for row in client.query(
+            &format!(
+                "SELECT n.nspname || '.' || c.relname AS name, c.relkind = 'm' AS materialized, \
+                pg_get_viewdef(c.oid, true) AS definition \
+                FROM pg_class c \
+                JOIN pg_namespace n ON n.oid = c.relnamespace \
+                WHERE c.relkind IN ('v', 'm') AND {} \
+                AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = c.oid AND e.deptype = 'e')",
+                USER_SCHEMAS
+            ),
+            &[],
+        )? {
+            let name: String = row.get("name");
+            schema.views.insert(name.clone(), View {
+                name,
+                materialized: row.get("materialized"),
+                definition: row.get("definition"),
+            });
+        }
+
+        for row in client.query(
+            &format!(
+                "SELECT n.nspname || '.' || p.proname || '(' || pg_get_function_identity_arguments(p.oid) || ')' AS signature, \
+                pg_get_functiondef(p.oid) AS definition \
+                FROM pg_proc p \
+                JOIN pg_namespace n ON n.oid = p.pronamespace \
+                WHERE p.prokind IN ('f', 'p') AND {} \
+                AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = p.oid AND e.deptype = 'e')",
+                USER_SCHEMAS
+            ),
+            &[],
+        )? {
+            let signature: String = row.get("signature");
+            schema.functions.insert(signature.clone(), Function { signature, definition: row.get("definition") });
+        }
+
+        Ok(schema)
+    }
+
+    /// Renders the schema as SQL, one object per statement in a stable order that can be replayed
+    /// into an empty database.
+    pub fn to_sql(&self) -> String {
+        let mut statements: Vec<String> = Vec::new();
+        statements.extend(self.schemas.iter().map(|name| format!("CREATE SCHEMA {};", name)));
+        statements.extend(self.sequences.values().map(|sequence| sequence.to_sql()));
+        statements.extend(self.tables.values().map(|table| table.to_sql()));
+        statements.extend(self.tables.values().flat_map(|table| table.foreign_keys_sql()));
+        statements.extend(self.indexes.values().map(|index| format!("{};", index.definition)));
+        statements.extend(self.functions.values().map(|function| format!("{};", function.definition.trim_end())));
+        statements.extend(self.views.values().map(|view| format!("{};", view.to_sql())));
+
+        let mut sql = statements.join("\n\n");
+        sql.push('\n');
+        sql
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::lock_db_client;
+
+    #[test]
+    fn test_to_sql() {
+        let mut schema = Schema::default();
+        schema.tables.insert("public.users".to_string(), Table {
+            name: "public.users".to_string(),
+            columns: vec![
+                Column { name: "id".to_string(), data_type: "integer".to_string(), not_null: true, default: None },
+                Column { name: "name".to_string(), data_type: "text".to_string(), not_null: false, default: Some("'anonymous'::text".to_string()) },
+            ],
+            constraints: BTreeMap::from([("users_pkey".to_string(), Constraint {
+                name: "users_pkey".to_string(),
+                definition: "PRIMARY KEY (id)".to_string(),
+            })]),
+        });
+        schema.indexes.insert("public.users_name_idx".to_string(), Index {
+            name: "public.users_name_idx".to_string(),
+            table: "public.users".to_string(),
+            definition: "CREATE INDEX users_name_idx ON public.users USING btree (name)".to_string(),
+        });
+
+        assert_eq!(
+            schema.to_sql(),
+            "CREATE TABLE public.users (\n    id integer NOT NULL,\n    name text DEFAULT 'anonymous'::text,\n    CONSTRAINT users_pkey PRIMARY KEY (id)\n);\n\n\
+            CREATE INDEX users_name_idx ON public.users USING btree (name);\n"
+        );
+    }
+
+    #[test]
+    fn test_introspect() {
+        let mut db_client = lock_db_client();
+        db_client.run_migrations(true, true, None, None).unwrap();
+        let schema = Schema::introspect(&mut db_client.client).unwrap();
+
+        let tables: Vec<&str> = schema.tables.keys().map(|name| name.as_str()).collect();
+        assert_eq!(tables, vec!["public.users", "public.wallets"]);
+        let users = &schema.tables["public.users"];
+        assert_eq!(users.columns[1].to_sql(), "username character varying(255) NOT NULL");
+        assert_eq!(
+            schema.tables["public.wallets"].constraints["wallets_user_id_fkey"].definition,
+            "FOREIGN KEY (user_id) REFERENCES users(id)"
+        );
+        let sequences: Vec<&str> = schema.sequences.keys().map(|name| name.as_str()).collect();
+        assert_eq!(sequences, vec!["public.users_id_seq", "public.wallets_id_seq"]);
+    }
+
+    #[test]
+    fn test_introspect_user_tables_named_like_bookkeeping() {
+        let mut db_client = lock_db_client();
+        db_client.client.batch_execute("CREATE SCHEMA app; CREATE TABLE app.history (id SERIAL PRIMARY KEY)").unwrap();
+        let schema = Schema::introspect(&mut db_client.client).unwrap();
+
+        let tables: Vec<&str> = schema.tables.keys().map(|name| name.as_str()).collect();
+        assert_eq!(tables, vec!["app.history"]);
+        let sequences: Vec<&str> = schema.sequences.keys().map(|name| name.as_str()).collect();
+        assert_eq!(sequences, vec!["app.history_id_seq"]);
+    }
+}
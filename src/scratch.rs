@@ -0,0 +1,76 @@
+use postgres::{Client, Error, NoTls};
+use uuid::Uuid;
+
+/// A temporary database on the same server as `database_url`, dropped again on `Drop`.
+pub struct ScratchDatabase {
+    admin: Client,
+    name: String,
+    url: String,
+}
+
+impl ScratchDatabase {
+    pub fn create(database_url: &str) -> Result<Self, Error> {
+        ScratchDatabase::create_from(database_url, None)
+    }
+
+    /// Creates the database as a copy of `template`, which must have no open connections.
+    pub fn create_from(database_url: &str, template: Option<&str>) -> Result<Self, Error> {
+        let mut admin = Client::connect(database_url, NoTls)?;
+        let name = format!("pg_migrate_scratch_{}", Uuid::new_v4().simple());
+        match template {
+            Some(template) => admin.batch_execute(&format!("CREATE DATABASE {} TEMPLATE {}", name, template))?,
+            None => admin.batch_execute(&format!("CREATE DATABASE {}", name))?,
+        }
+        let url = with_database(database_url, &name);
+        Ok(ScratchDatabase { admin, name, url })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn connect(&self) -> Result<Client, Error> {
+        Client::connect(&self.url, NoTls)
+    }
+}
+
+impl Drop for ScratchDatabase {
+    fn drop(&mut self) {
+        let _ = self.admin.batch_execute(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name));
+    }
+}
+
+/// Points a `postgresql://` URL or `key=value` connection string at another database.
+pub fn with_database(database_url: &str, database: &str) -> String {
+    if let Some(scheme_end) = database_url.find("://") {
+        let authority_start = scheme_end + 3;
+        let query_start = database_url.find('?').unwrap_or(database_url.len());
+        let path_start = database_url[authority_start..query_start].find('/')
+            .map_or(query_start, |i| authority_start + i);
+        format!("{}/{}{}", &database_url[..path_start], database, &database_url[query_start..])
+    } else {
+        let mut parts: Vec<String> = database_url.split_whitespace()
+            .filter(|part| !part.starts_with("dbname="))
+            .map(|part| part.to_string())
+            .collect();
+        parts.push(format!("dbname={}", database));
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_database() {
+        assert_eq!(with_database("postgresql://user:pw@localhost:5432/app", "scratch"), "postgresql://user:pw@localhost:5432/scratch");
+        assert_eq!(with_database("postgres://localhost/app?sslmode=disable", "scratch"), "postgres://localhost/scratch?sslmode=disable");
+        assert_eq!(with_database("postgresql://localhost", "scratch"), "postgresql://localhost/scratch");
+        assert_eq!(with_database("host=localhost dbname=app user=me", "scratch"), "host=localhost user=me dbname=scratch");
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::migration::read_headers;
+
+/// A seed file: `<SEED_DIR>/<name>.sql` runs in every environment, `<SEED_DIR>/<env>/<name>.sql` only in `env`.
+#[derive(Debug, Clone)]
+pub struct Seed {
+    pub name: String,
+    pub env: Option<String>,
+    pub path: PathBuf,
+    /// Revision that must be applied before the seed can run, from its `-- Requires` header.
+    pub requires: Option<String>,
+}
+
+impl Seed {
+    pub fn sql(&self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+}
+
+/// Loads the shared seeds followed by the seeds of `env`, each sorted by file name.
+pub fn load_seeds(dir: &Path, env: Option<&str>) -> io::Result<Vec<Seed>> {
+    let mut seeds = load_seed_files(dir, None)?;
+    if let Some(env) = env {
+        let env_dir = dir.join(env);
+        if !env_dir.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No seeds for environment {}", env)));
+        }
+        seeds.extend(load_seed_files(&env_dir, Some(env))?);
+    }
+    Ok(seeds)
+}
+
+fn load_seed_files(dir: &Path, env: Option<&str>) -> io::Result<Vec<Seed>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut seeds = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if !path.is_file() || !file_name.ends_with(".sql") {
+            continue;
+        }
+        let requires = read_headers(&path)?.into_iter()
+            .find(|(key, _)| key == "Requires")
+            .map(|(_, value)| value);
+        let name = match env {
+            Some(env) => format!("{}/{}", env, file_name),
+            None => file_name,
+        };
+        seeds.push(Seed { name, env: env.map(|env| env.to_string()), path, requires });
+    }
+    seeds.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(seeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::TempDir;
+
+    #[test]
+    fn test_load_seeds() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.join("dev")).unwrap();
+        fs::write(dir.join("02_roles.sql"), "-- Requires: f44e620f-60e0-4470-8904-44b4022b11a5\nSELECT 1;\n").unwrap();
+        fs::write(dir.join("01_currencies.sql"), "SELECT 1;\n").unwrap();
+        fs::write(dir.join("dev").join("01_users.sql"), "SELECT 1;\n").unwrap();
+
+        let shared: Vec<String> = load_seeds(&dir, None).unwrap().into_iter().map(|seed| seed.name).collect();
+        assert_eq!(shared, vec!["01_currencies.sql", "02_roles.sql"]);
+
+        let dev = load_seeds(&dir, Some("dev")).unwrap();
+        let names: Vec<&str> = dev.iter().map(|seed| seed.name.as_str()).collect();
+        assert_eq!(names, vec!["01_currencies.sql", "02_roles.sql", "dev/01_users.sql"]);
+        assert_eq!(dev[1].requires.as_deref(), Some("f44e620f-60e0-4470-8904-44b4022b11a5"));
+        assert_eq!(dev[2].env.as_deref(), Some("dev"));
+
+        assert!(load_seeds(&dir, Some("staging")).is_err());
+    }
+}
@@ -1,11 +1,14 @@
 use std::env;
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
 use postgres::{Client, NoTls, Error};
 use crate::DbClient;
 use dotenv::dotenv;
-use std::sync::{Mutex, Once};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use uuid::Uuid;
 
-static INIT: Once = Once::new();
-static mut DB_CLIENT: Option<Mutex<DbClient>> = None;
+static DB_CLIENT: OnceLock<Mutex<DbClient>> = OnceLock::new();
 
 fn setup_test_database() -> Result<(), Error> {
     dotenv().ok();
@@ -25,13 +28,120 @@ pub fn setup() {
 }
 
 pub fn get_db_client() -> &'static Mutex<DbClient> {
-    unsafe {
-        INIT.call_once(|| {
-            setup();
-            let test_database_url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
-            let db_client = DbClient::new(&test_database_url).expect("Failed to initialize database");
-            DB_CLIENT = Some(Mutex::new(db_client));
-        });
-        DB_CLIENT.as_ref().expect("DB Client not initialized")
+    DB_CLIENT.get_or_init(|| {
+        setup();
+        let test_database_url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let db_client = DbClient::new(&test_database_url).expect("Failed to initialize database");
+        Mutex::new(db_client)
+    })
+}
+
+/// The shared test client, locked until dropped. Dropping it empties the test database and
+/// replaces the client with a new one, so settings and migrations a test leaves behind do not
+/// reach the next test, also when the test fails.
+pub struct TestClient {
+    db_client: MutexGuard<'static, DbClient>,
+    dir: Option<TempDir>,
+}
+
+pub fn lock_db_client() -> TestClient {
+    let db_client = get_db_client().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    TestClient { db_client, dir: None }
+}
+
+/// `lock_db_client` pointed at an empty migration directory, filled with `write_migration`.
+pub fn lock_db_client_with_migrations() -> TestClient {
+    let mut test_client = lock_db_client();
+    let dir = TempDir::new();
+    test_client.set_migration_dir(dir.to_str().unwrap());
+    test_client.dir = Some(dir);
+    test_client
+}
+
+impl TestClient {
+    /// The migration directory of `lock_db_client_with_migrations`.
+    pub fn dir(&self) -> &Path {
+        self.dir.as_deref().expect("Not locked with lock_db_client_with_migrations")
+    }
+
+    /// Writes a migration to the migration directory, see `write_migration`.
+    pub fn write_migration(&self, revision: &str, down_revision: Option<&str>, name: &str, up_sql: &str, down_sql: &str) {
+        write_migration(self.dir(), revision, down_revision, name, up_sql, down_sql);
+    }
+}
+
+/// Writes `<revision>_<name>_up.sql` and `_down.sql` to `dir`, each with a `Revision` header, a
+/// `Down Revision` header for `Some` parent and the given SQL, which may start with more headers.
+pub fn write_migration(dir: &Path, revision: &str, down_revision: Option<&str>, name: &str, up_sql: &str, down_sql: &str) {
+    let mut headers = format!("-- Revision: {}\n", revision);
+    if let Some(down_revision) = down_revision {
+        headers.push_str(&format!("-- Down Revision: {}\n", down_revision));
+    }
+    let stem = format!("{}_{}", revision, name);
+    fs::write(dir.join(format!("{}_up.sql", stem)), format!("{}{}", headers, up_sql)).unwrap();
+    fs::write(dir.join(format!("{}_down.sql", stem)), format!("{}{}", headers, down_sql)).unwrap();
+}
+
+impl Deref for TestClient {
+    type Target = DbClient;
+
+    fn deref(&self) -> &DbClient {
+        &self.db_client
+    }
+}
+
+impl DerefMut for TestClient {
+    fn deref_mut(&mut self) -> &mut DbClient {
+        &mut self.db_client
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        let database_url = self.db_client.database_url.clone();
+        let reset = DbClient::_reset_database(&database_url).and_then(|_| DbClient::new(&database_url));
+        match reset {
+            Ok(db_client) => *self.db_client = db_client,
+            Err(error) => eprintln!("Failed to reset the test database: {}", error),
+        }
+    }
+}
+
+/// A uniquely named directory under the system temp dir, removed with its contents when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        let path = env::temp_dir().join(format!("pg_migrate_{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).expect("Failed to create temp dir");
+        TempDir { path }
+    }
+}
+
+impl Default for TempDir {
+    fn default() -> Self {
+        TempDir::new()
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
     }
 }
@@ -0,0 +1,164 @@
+//! Squashing a range of migrations into one.
+use std::fs;
+use std::path::Path;
+use crate::error::MigrateError;
+use crate::migration::{load_migrations, strip_headers, write_new_migration, Migration};
+use crate::naming::NamingScheme;
+use crate::revision;
+
+/// Replaces the migrations in `dir` from `from` to `to` with a single migration, named by
+/// `naming`, running their up SQL in order and their down SQL in reverse, and points the
+/// migrations after `to` at it.
+///
+/// `from` and `to` accept the references of `revision::resolve`; `current` is only called for
+/// references to the current revision. The range must be a straight line of revisions.
+pub fn squash<F>(dir: &Path, naming: &NamingScheme, from: &str, to: &str, name: Option<&str>, mut current: F) -> Result<(), MigrateError>
+where
+    F: FnMut() -> Result<Option<String>, MigrateError>,
+{
+    let migrations = load_migrations(dir)?;
+    let mut position = |reference: &str| -> Result<usize, MigrateError> {
+        let revision = revision::resolve(&migrations, reference, &mut current)?
+            .ok_or_else(|| MigrateError::UnknownRevision(reference.to_string()))?;
+        Ok(migrations.iter().position(|m| m.revision == revision).unwrap())
+    };
+    let start = position(from)?;
+    let end = position(to)?;
+    if start >= end {
+        return Err(MigrateError::InvalidSquashRange(format!("{} does not come before {}", from, to)));
+    }
+
+    let (range, rest): (Vec<&Migration>, Vec<&Migration>) = migrations.iter()
+        .partition(|m| (start..=end).contains(&m.order));
+    let revisions: Vec<String> = range.iter().map(|m| m.revision.clone()).collect();
+    for pair in range.windows(2) {
+        if pair[1].down_revisions != [pair[0].revision.clone()] {
+            return Err(MigrateError::InvalidSquashRange(
+                format!("{} does not directly follow {}", pair[1].revision, pair[0].revision),
+            ));
+        }
+    }
+    let last = &revisions[revisions.len() - 1];
+    for migration in &rest {
+        if let Some(parent) = migration.down_revisions.iter().find(|parent| revisions.contains(parent) && *parent != last) {
+            return Err(MigrateError::InvalidSquashRange(
+                format!("{} branches off {} inside the range", migration.revision, parent),
+            ));
+        }
+    }
+
+    let mut up_sql = String::new();
+    let mut down_sql = String::new();
+    for migration in &range {
+        up_sql.push_str(&format!("-- Squashed from {} {}\n{}\n", migration.revision, migration.name, strip_headers(&migration.up_sql()?)));
+    }
+    for migration in range.iter().rev() {
+        down_sql.push_str(&format!("-- Squashed from {} {}\n{}\n", migration.revision, migration.name, strip_headers(&migration.down_sql()?)));
+    }
+
+    let first = range[0];
+    let mut depends_on: Vec<String> = Vec::new();
+    for dependency in range.iter().flat_map(|m| m.depends_on.iter()) {
+        if !revisions.contains(dependency) && !depends_on.contains(dependency) {
+            depends_on.push(dependency.clone());
+        }
+    }
+    let down_revision = if first.down_revisions.is_empty() { "None".to_string() } else { first.down_revisions.join(", ") };
+    let mut headers = vec![("Down Revision", down_revision)];
+    if !depends_on.is_empty() {
+        headers.push(("Depends On", depends_on.join(", ")));
+    }
+    headers.push(("Replaces", revisions.join(", ")));
+    let stem = write_new_migration(dir, naming, name.unwrap_or("squashed"), &headers, &up_sql, &down_sql)?;
+    let squashed = load_migrations(dir)?.into_iter()
+        .find(|m| m.sort_key == stem)
+        .map(|m| m.revision)
+        .unwrap_or(stem.clone());
+
+    for migration in &rest {
+        if migration.down_revisions.contains(last) {
+            let parents: Vec<String> = migration.down_revisions.iter()
+                .map(|parent| if parent == last { squashed.clone() } else { parent.clone() })
+                .collect();
+            migration.set_down_revisions(&parents)?;
+            println!("Updated Down Revision of {}", migration.revision);
+        }
+        if migration.depends_on.iter().any(|dependency| revisions.contains(dependency)) {
+            let mut dependencies: Vec<String> = Vec::new();
+            for dependency in &migration.depends_on {
+                let dependency = if revisions.contains(dependency) { squashed.clone() } else { dependency.clone() };
+                if !dependencies.contains(&dependency) {
+                    dependencies.push(dependency);
+                }
+            }
+            migration.set_depends_on(&dependencies)?;
+            println!("Updated Depends On of {}", migration.revision);
+        }
+    }
+
+    for migration in &range {
+        if migration.is_directory() {
+            fs::remove_dir_all(&migration.path)?;
+        } else {
+            for file in migration.up_files.iter().chain(migration.down_files.iter()) {
+                fs::remove_file(file)?;
+            }
+        }
+    }
+    println!("Squashed {} migrations into {}", range.len(), stem);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::{write_migration, TempDir};
+
+    fn offline() -> Result<Option<String>, MigrateError> {
+        Err(MigrateError::UnknownRevision("current".to_string()))
+    }
+
+    fn write_chain(dir: &Path) {
+        write_migration(dir, "V0001", None, "sq_a", "CREATE TABLE sq_a (id INT);\n", "DROP TABLE sq_a;\n");
+        write_migration(dir, "V0002", Some("V0001"), "sq_b", "CREATE TABLE sq_b (id INT);\n", "DROP TABLE sq_b;\n");
+        write_migration(dir, "V0003", Some("V0002"), "sq_c", "CREATE TABLE sq_c (id INT);\n", "DROP TABLE sq_c;\n");
+        write_migration(dir, "V0004", Some("V0003"), "sq_d", "-- Depends On: V0002\nCREATE TABLE sq_d (id INT);\n", "DROP TABLE sq_d;\n");
+    }
+
+    #[test]
+    fn test_squash() {
+        let dir = TempDir::new();
+        write_chain(&dir);
+
+        squash(&dir, &NamingScheme::new("sequential"), "V0001", "V0002", None, offline).unwrap();
+        let up_sql = fs::read_to_string(dir.join("V0005_squashed_up.sql")).unwrap();
+        assert!(up_sql.contains("-- Down Revision: None\n-- Replaces: V0001, V0002\n"));
+        assert!(up_sql.find("CREATE TABLE sq_a").unwrap() < up_sql.find("CREATE TABLE sq_b").unwrap());
+        let down_sql = fs::read_to_string(dir.join("V0005_squashed_down.sql")).unwrap();
+        assert!(down_sql.find("DROP TABLE sq_b").unwrap() < down_sql.find("DROP TABLE sq_a").unwrap());
+        assert!(!dir.join("V0001_sq_a_up.sql").exists());
+        assert!(!dir.join("V0002_sq_b_down.sql").exists());
+
+        let migrations = load_migrations(&dir).unwrap();
+        let revisions: Vec<&str> = migrations.iter().map(|m| m.revision.as_str()).collect();
+        assert_eq!(revisions, vec!["V0005", "V0003", "V0004"]);
+        assert_eq!(migrations[1].down_revisions, vec!["V0005"]);
+        assert_eq!(migrations[2].depends_on, vec!["V0005"]);
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        let dir = TempDir::new();
+        write_chain(&dir);
+        write_migration(&dir, "V0005", Some("V0002"), "branch", "", "");
+        let naming = NamingScheme::new("sequential");
+
+        let backwards = squash(&dir, &naming, "V0002", "V0001", None, offline);
+        assert!(matches!(backwards, Err(MigrateError::InvalidSquashRange(reason)) if reason == "V0002 does not come before V0001"));
+        let branching = squash(&dir, &naming, "V0001", "V0003", None, offline);
+        assert!(matches!(branching, Err(MigrateError::InvalidSquashRange(reason)) if reason == "V0005 branches off V0002 inside the range"));
+        let current = squash(&dir, &naming, "V0001", "current", None, offline);
+        assert!(matches!(current, Err(MigrateError::UnknownRevision(revision)) if revision == "current"));
+        assert_eq!(load_migrations(&dir).unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::thread;
+use postgres::{Client, Error};
+use serde::Deserialize;
+
+/// `[tenants]` of `pg_migrate.toml`: which schemas hold a tenant, one migrated copy each.
+///
+/// The schemas from `schemas`, `query` and `pattern` are combined.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TenantsConfig {
+    /// Schema names, created when missing.
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// Query returning the schema names in its first column.
+    pub query: Option<String>,
+    /// `LIKE` pattern matched against the existing schemas, e.g. `tenant_%`.
+    pub pattern: Option<String>,
+    /// Number of tenants migrated at the same time, 1 by default.
+    pub concurrency: Option<usize>,
+}
+
+impl TenantsConfig {
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty() && self.query.is_none() && self.pattern.is_none()
+    }
+
+    /// The tenant schemas, sorted by name.
+    pub fn schemas(&self, client: &mut Client) -> Result<Vec<String>, Error> {
+        let mut schemas: BTreeSet<String> = self.schemas.iter().cloned().collect();
+        if let Some(query) = &self.query {
+            for row in client.query(query.as_str(), &[])? {
+                schemas.insert(row.get(0));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            for row in client.query("SELECT nspname::TEXT FROM pg_namespace WHERE nspname LIKE $1", &[pattern])? {
+                schemas.insert(row.get(0));
+            }
+        }
+        Ok(schemas.into_iter().collect())
+    }
+}
+
+/// Quotes `name` as an SQL identifier.
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Runs `f` for every item on up to `concurrency` threads, returning the results in item order.
+pub fn run_parallel<T, R, F>(items: Vec<T>, concurrency: usize, f: F) -> Vec<(T, R)>
+where
+    T: Send,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = concurrency.clamp(1, items.len().max(1));
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    let queue = Mutex::new(items.into_iter().enumerate());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let Some((index, item)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = f(&item);
+                results.lock().unwrap().push((index, item, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _, _)| *index);
+    results.into_iter().map(|(_, item, result)| (item, result)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_ident() {
+        assert_eq!(quote_ident("tenant_a"), "\"tenant_a\"");
+        assert_eq!(quote_ident("odd\"name"), "\"odd\"\"name\"");
+    }
+
+    #[test]
+    fn test_run_parallel() {
+        let results = run_parallel((1..=10).collect(), 3, |n: &i32| n * n);
+        assert_eq!(results.iter().map(|(n, _)| *n).collect::<Vec<_>>(), (1..=10).collect::<Vec<_>>());
+        assert_eq!(results[9].1, 100);
+        assert!(run_parallel(Vec::<i32>::new(), 4, |n: &i32| *n).is_empty());
+    }
+}
@@ -0,0 +1,352 @@
+//! Databases for tests, one per test instead of the single shared one in `setup`.
+//!
+//! ```no_run
+//! use pg_migrate::testing::TestDatabase;
+//!
+//! #[test]
+//! fn creates_user() {
+//!     let mut db = TestDatabase::from_template().unwrap();
+//!     db.client().execute("INSERT INTO users (username, password) VALUES ('a', 'b')", &[]).unwrap();
+//! }
+//! ```
+//!
+//! Data migrations are tested with a [`MigrationTestContext`], which moves an empty database
+//! between revisions:
+//!
+//! ```no_run
+//! use pg_migrate::testing::MigrationTestContext;
+//!
+//! let mut context = MigrationTestContext::new().unwrap();
+//! context.migrate_to("f44e620f-60e0-4470-8904-44b4022b11a5").unwrap();
+//! context.client().execute("INSERT INTO users (username, password) VALUES ('a', 'b')", &[]).unwrap();
+//! let applied = context.step_up().unwrap();
+//! assert_eq!(applied.name, "add wallet");
+//! ```
+//!
+//! The synchronous `postgres` client cannot run inside an async runtime, so the database is created
+//! and dropped on a separate thread. In `#[tokio::test]` functions connect to [`TestDatabase::url`]
+//! with an async driver instead of calling [`TestDatabase::client`].
+use std::env;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use dotenv::dotenv;
+use postgres::{Client, NoTls};
+use sha2::{Digest, Sha256};
+use crate::error::MigrateError;
+use crate::migration::{load_migrations, Migration};
+use crate::revision;
+use crate::scratch::{with_database, ScratchDatabase};
+use crate::DbClient;
+
+static TEMPLATE: OnceLock<Mutex<Option<String>>> = OnceLock::new();
+
+/// A uniquely named database, dropped together with its connection on `Drop`.
+pub struct TestDatabase {
+    db_client: Option<DbClient>,
+    database: Option<ScratchDatabase>,
+}
+
+impl TestDatabase {
+    /// Creates a database on the `DATABASE_URL` server and upgrades it to head.
+    pub fn new() -> Result<Self, MigrateError> {
+        off_runtime(|| {
+            let mut test_database = TestDatabase::create(None)?;
+            test_database.db_client().run_migrations(true, true, None, None)?;
+            Ok(test_database)
+        })
+    }
+
+    /// Creates a database with only pg_migrate's bookkeeping tables, for tests that run the
+    /// migrations themselves.
+    pub fn empty() -> Result<Self, MigrateError> {
+        off_runtime(|| TestDatabase::create(None))
+    }
+
+    /// Creates a database as a copy of a template already upgraded to head.
+    ///
+    /// The template is named after the checksums of the migrations, so it is built once and reused
+    /// by later tests and test runs until a migration changes.
+    pub fn from_template() -> Result<Self, MigrateError> {
+        off_runtime(|| {
+            let mut template = TEMPLATE.get_or_init(|| Mutex::new(None)).lock().unwrap();
+            if template.is_none() {
+                *template = Some(build_template()?);
+            }
+            TestDatabase::create(template.as_deref())
+        })
+    }
+
+    fn create(template: Option<&str>) -> Result<Self, MigrateError> {
+        let database = ScratchDatabase::create_from(&database_url(), template)?;
+        let db_client = test_db_client(database.url())?;
+        Ok(TestDatabase { db_client: Some(db_client), database: Some(database) })
+    }
+
+    pub fn name(&self) -> &str {
+        self.database.as_ref().unwrap().name()
+    }
+
+    pub fn url(&self) -> &str {
+        self.database.as_ref().unwrap().url()
+    }
+
+    /// The connection to the database. Not usable inside an async runtime.
+    pub fn client(&mut self) -> &mut Client {
+        &mut self.db_client().client
+    }
+
+    /// A client for running migrations against the database.
+    pub fn db_client(&mut self) -> &mut DbClient {
+        self.db_client.as_mut().unwrap()
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let db_client = self.db_client.take();
+        let database = self.database.take();
+        off_runtime(move || {
+            drop(db_client);
+            drop(database);
+        });
+    }
+}
+
+/// Moves a test database between revisions, to set up fixtures before a data migration runs
+/// and check its result afterwards.
+pub struct MigrationTestContext {
+    database: TestDatabase,
+}
+
+impl MigrationTestContext {
+    /// Creates a context on an empty test database, at base.
+    pub fn new() -> Result<Self, MigrateError> {
+        Ok(MigrationTestContext { database: TestDatabase::empty()? })
+    }
+
+    /// Upgrades or downgrades until `revision` is the current revision. Accepts the same
+    /// references as `upgrade migration-id`, e.g. a prefix, `head~1` or `base`.
+    pub fn migrate_to(&mut self, revision: &str) -> Result<(), MigrateError> {
+        let migrations = self.migrations()?;
+        let target = match revision::resolve(&migrations, revision, || self.current())? {
+            Some(target) => target,
+            None => "base".to_string(),
+        };
+        let position = migrations.iter().position(|migration| migration.revision == target).map_or(0, |i| i + 1);
+        let applied = self.applied(&migrations)?;
+        if position == applied {
+            return Ok(());
+        }
+        self.database.db_client().run_migrations(position > applied, false, Some(&target), None)
+    }
+
+    /// Applies the next pending migration and returns it.
+    pub fn step_up(&mut self) -> Result<Migration, MigrateError> {
+        let migrations = self.migrations()?;
+        let applied = self.applied(&migrations)?;
+        let migration = migrations.get(applied).cloned()
+            .ok_or_else(|| MigrateError::NothingToMigrate("already at head".to_string()))?;
+        self.database.db_client().run_migrations(true, false, None, Some(&1))?;
+        Ok(migration)
+    }
+
+    /// Reverts the current migration and returns it.
+    pub fn step_down(&mut self) -> Result<Migration, MigrateError> {
+        let migrations = self.migrations()?;
+        let applied = self.applied(&migrations)?;
+        if applied == 0 {
+            return Err(MigrateError::NothingToMigrate("already at base".to_string()));
+        }
+        self.database.db_client().run_migrations(false, false, None, Some(&1))?;
+        Ok(migrations[applied - 1].clone())
+    }
+
+    /// The current revision, `None` at base.
+    pub fn current(&mut self) -> Result<Option<String>, MigrateError> {
+        self.database.db_client().current_revision()
+    }
+
+    pub fn client(&mut self) -> &mut Client {
+        self.database.client()
+    }
+
+    /// The underlying client, e.g. to point it at another migration directory.
+    pub fn db_client(&mut self) -> &mut DbClient {
+        self.database.db_client()
+    }
+
+    fn migrations(&mut self) -> Result<Vec<Migration>, MigrateError> {
+        let db_client = self.database.db_client();
+        let mut migrations = db_client._load_migrations()?;
+        db_client._sort_migrations(&mut migrations, true);
+        Ok(migrations)
+    }
+
+    /// Number of migrations up to and including the current one.
+    fn applied(&mut self, migrations: &[Migration]) -> Result<usize, MigrateError> {
+        Ok(match self.current()? {
+            Some(current) => migrations.iter().position(|migration| migration.revision == current)
+                .ok_or(MigrateError::UnknownRevision(current))? + 1,
+            None => 0,
+        })
+    }
+}
+
+fn database_url() -> String {
+    dotenv().ok();
+    env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+}
+
+/// A client that leaves `schema.sql` alone and skips the shadow check, whatever pg_migrate.toml
+/// says, since test databases come and go on every run.
+fn test_db_client(url: &str) -> Result<DbClient, MigrateError> {
+    let mut db_client = DbClient::new(url)?;
+    db_client.set_dump_schema(false);
+    db_client.set_shadow_check(false);
+    Ok(db_client)
+}
+
+/// Creates the template for the current migrations unless it exists, and returns its name.
+/// Templates built for earlier versions of the migrations are dropped.
+fn build_template() -> Result<String, MigrateError> {
+    let database_url = database_url();
+    let mut hasher = Sha256::new();
+    for migration in load_migrations(Path::new(&DbClient::get_migration_dir()))? {
+        hasher.update(migration.checksum()?);
+    }
+    let digest = hasher.finalize();
+    let name = format!("pg_migrate_template_{}", digest.iter().take(8).map(|b| format!("{:02x}", b)).collect::<String>());
+
+    let mut admin = Client::connect(&database_url, NoTls)?;
+    drop_stale_templates(&mut admin, &name)?;
+    if admin.query_opt("SELECT 1 FROM pg_database WHERE datname = $1", &[&name])?.is_some() {
+        return Ok(name);
+    }
+
+    let building = format!("{}_{}", name, std::process::id());
+    admin.batch_execute(&format!("CREATE DATABASE {}", building))?;
+    let migrated = test_db_client(&with_database(&database_url, &building))
+        .and_then(|mut db_client| db_client.run_migrations(true, true, None, None));
+    if let Err(error) = migrated {
+        admin.batch_execute(&format!("DROP DATABASE {} WITH (FORCE)", building))?;
+        return Err(error);
+    }
+    if admin.batch_execute(&format!("ALTER DATABASE {} RENAME TO {}", building, name)).is_err() {
+        // Another test run built the same template first.
+        admin.batch_execute(&format!("DROP DATABASE {} WITH (FORCE)", building))?;
+    }
+    Ok(name)
+}
+
+/// Drops the templates other than `current`. Templates still being copied by another test run
+/// cannot be dropped and are left for the next run; databases being built carry a pid suffix and
+/// do not match.
+fn drop_stale_templates(admin: &mut Client, current: &str) -> Result<(), MigrateError> {
+    let stale = admin.query(
+        "SELECT datname FROM pg_database WHERE datname ~ '^pg_migrate_template_[0-9a-f]{16}$' AND datname <> $1",
+        &[&current],
+    )?;
+    for row in stale {
+        let name: String = row.get(0);
+        let _ = admin.batch_execute(&format!("DROP DATABASE IF EXISTS {}", name));
+    }
+    Ok(())
+}
+
+/// Runs `f` on its own thread, where the blocking `postgres` client may start its runtime.
+fn off_runtime<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| scope.spawn(f).join().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database_exists(name: &str) -> bool {
+        off_runtime(|| {
+            let mut admin = Client::connect(&database_url(), NoTls).unwrap();
+            admin.query_opt("SELECT 1 FROM pg_database WHERE datname = $1", &[&name]).unwrap().is_some()
+        })
+    }
+
+    #[test]
+    fn test_database_per_test() {
+        let mut first = TestDatabase::new().unwrap();
+        let mut second = TestDatabase::from_template().unwrap();
+        let empty = TestDatabase::empty().unwrap();
+        first.client().execute("INSERT INTO users (username, password) VALUES ('first', 'secret')", &[]).unwrap();
+
+        let first_users: i64 = first.client().query_one("SELECT count(*) FROM users", &[]).unwrap().get(0);
+        assert_eq!(first_users, 1);
+        let second_users: i64 = second.client().query_one("SELECT count(*) FROM users", &[]).unwrap().get(0);
+        assert_eq!(second_users, 0);
+        let second_current: Option<String> = second.client()
+            .query_one("SELECT migration_id FROM migrations", &[]).unwrap().get(0);
+        assert_eq!(second_current.as_deref(), Some("622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd"));
+
+        let names = [first.name().to_string(), second.name().to_string(), empty.name().to_string()];
+        drop((first, second, empty));
+        assert!(names.iter().all(|name| !database_exists(name)));
+    }
+
+    #[test]
+    fn test_migration_test_context() {
+        let mut context = MigrationTestContext::new().unwrap();
+        assert_eq!(context.current().unwrap(), None);
+
+        context.migrate_to("f44e620f-60e0-4470-8904-44b4022b11a5").unwrap();
+        context.client().execute("INSERT INTO users (username, password) VALUES ('fixture', 'secret')", &[]).unwrap();
+        let applied = context.step_up().unwrap();
+        assert_eq!(applied.name, "add wallet");
+        assert_eq!(context.current().unwrap().as_deref(), Some("622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd"));
+        let users: i64 = context.client().query_one("SELECT count(*) FROM users", &[]).unwrap().get(0);
+        assert_eq!(users, 1);
+        assert!(matches!(context.step_up(), Err(MigrateError::NothingToMigrate(_))));
+
+        context.migrate_to("f44e620f-60e0-4470-8904-44b4022b11a5").unwrap();
+        assert_eq!(context.current().unwrap().as_deref(), Some("f44e620f-60e0-4470-8904-44b4022b11a5"));
+        assert!(matches!(context.migrate_to("missing"), Err(MigrateError::UnknownRevision(revision)) if revision == "missing"));
+        context.migrate_to("head").unwrap();
+        context.migrate_to("head~1").unwrap();
+        assert_eq!(context.current().unwrap().as_deref(), Some("f44e620f-60e0-4470-8904-44b4022b11a5"));
+
+        let reverted = context.step_down().unwrap();
+        assert_eq!(reverted.revision, "f44e620f-60e0-4470-8904-44b4022b11a5");
+        assert_eq!(context.current().unwrap(), None);
+        assert!(matches!(context.step_down(), Err(MigrateError::NothingToMigrate(_))));
+    }
+
+    #[test]
+    fn test_build_template_drops_stale_templates() {
+        let stale = "pg_migrate_template_0000000000000000";
+        off_runtime(|| {
+            let mut admin = Client::connect(&database_url(), NoTls).unwrap();
+            admin.batch_execute(&format!("DROP DATABASE IF EXISTS {}", stale)).unwrap();
+            admin.batch_execute(&format!("CREATE DATABASE {}", stale)).unwrap();
+        });
+
+        let name = off_runtime(build_template).unwrap();
+
+        assert_ne!(name, stale);
+        assert!(database_exists(&name));
+        assert!(!database_exists(stale));
+    }
+
+    #[test]
+    fn test_test_database_skips_schema_dump_and_shadow_check() {
+        let mut database = TestDatabase::empty().unwrap();
+        assert!(!database.db_client().dump_schema);
+        assert!(!database.db_client().shadow_check);
+    }
+
+    #[tokio::test]
+    async fn test_inside_async_runtime() {
+        let database = TestDatabase::from_template().unwrap();
+        let name = database.name().to_string();
+        assert!(database.url().ends_with(&name));
+        assert!(database_exists(&name));
+        drop(database);
+        assert!(!database_exists(&name));
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::{self, Read};
+use gag::BufferRedirect;
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use crate::error::MigrateError;
+use crate::migration::strip_headers;
+use crate::DbClient;
+
+/// A migration as listed in the revision graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub revision: String,
+    pub name: String,
+    pub down_revisions: Vec<String>,
+    pub children: usize,
+    pub applied: bool,
+    pub current: bool,
+    pub up_sql: String,
+    pub down_sql: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Upgrade,
+    Downgrade,
+}
+
+/// State of the TUI: the revision graph, newest first and followed by base, the selection, a
+/// pending confirmation and the log of what was run.
+pub struct App {
+    rows: Vec<Row>,
+    selected: usize,
+    show_down: bool,
+    confirm: Option<Action>,
+    log: Vec<String>,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(rows: Vec<Row>) -> Self {
+        let selected = rows.iter().position(|row| row.current).unwrap_or(rows.len());
+        App { rows, selected, show_down: false, confirm: None, log: Vec::new(), quit: false }
+    }
+
+    /// Replaces the rows after migrations ran, keeping the selection in range.
+    pub fn set_rows(&mut self, rows: Vec<Row>) {
+        self.rows = rows;
+        self.selected = self.selected.min(self.rows.len());
+    }
+
+    /// Position of the current revision, `rows.len()` standing for base.
+    fn current(&self) -> usize {
+        self.rows.iter().position(|row| row.current).unwrap_or(self.rows.len())
+    }
+
+    fn target(&self) -> String {
+        self.rows.get(self.selected).map_or("base".to_string(), |row| row.revision.clone())
+    }
+
+    /// Targets to migrate to one at a time to reach the selected revision, so progress can be
+    /// shown after each migration.
+    pub fn steps(&self, action: Action) -> Vec<String> {
+        let current = self.current();
+        let revision = |index: usize| self.rows.get(index).map_or("base".to_string(), |row| row.revision.clone());
+        match action {
+            Action::Upgrade if self.selected < current => (self.selected..current).rev().map(revision).collect(),
+            Action::Downgrade if self.selected > current => (current + 1..=self.selected).map(revision).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Handles a key press and returns the action to run once it has been confirmed.
+    pub fn handle_key(&mut self, key: KeyCode) -> Option<Action> {
+        if let Some(action) = self.confirm {
+            self.confirm = None;
+            if matches!(key, KeyCode::Char('y') | KeyCode::Enter) {
+                return Some(action);
+            }
+            self.log.push("Cancelled".to_string());
+            return None;
+        }
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(self.rows.len()),
+            KeyCode::Tab => self.show_down = !self.show_down,
+            KeyCode::Char('u') => self.ask(Action::Upgrade),
+            KeyCode::Char('d') => self.ask(Action::Downgrade),
+            _ => {}
+        }
+        None
+    }
+
+    fn ask(&mut self, action: Action) {
+        if self.steps(action).is_empty() {
+            let direction = if action == Action::Upgrade { "upgrade" } else { "downgrade" };
+            self.log.push(format!("Nothing to {} to {}", direction, self.target()));
+        } else {
+            self.confirm = Some(action);
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let [main, log, help] = Layout::vertical([Constraint::Min(5), Constraint::Length(8), Constraint::Length(1)])
+            .areas(frame.area());
+        let [graph, preview] = Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(main);
+
+        let mut items: Vec<ListItem> = self.rows.iter().map(graph_line).map(ListItem::new).collect();
+        items.push(ListItem::new(Line::from(vec![
+            Span::styled("◇ ", Style::default().fg(Color::DarkGray)),
+            Span::raw(if self.current() == self.rows.len() { "base (current)" } else { "base" }),
+        ])));
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(" Revisions "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, graph, &mut ListState::default().with_selected(Some(self.selected)));
+
+        let (title, sql) = match self.rows.get(self.selected) {
+            Some(row) if self.show_down => (format!(" Down SQL {} ", row.revision), row.down_sql.as_str()),
+            Some(row) => (format!(" Up SQL {} ", row.revision), row.up_sql.as_str()),
+            None => (" base ".to_string(), "No migrations applied"),
+        };
+        let preview_widget = Paragraph::new(sql)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(preview_widget, preview);
+
+        let height = log.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self.log.iter().skip(self.log.len().saturating_sub(height)).map(|line| {
+            let style = if line.starts_with("Error") { Style::default().fg(Color::Red) } else { Style::default() };
+            Line::styled(line.as_str(), style)
+        }).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Log ")), log);
+        frame.render_widget(
+            Paragraph::new("↑/↓ select  tab up/down SQL  u upgrade to  d downgrade to  q quit")
+                .style(Style::default().fg(Color::DarkGray)),
+            help,
+        );
+
+        if let Some(action) = self.confirm {
+            let count = self.steps(action).len();
+            let direction = if action == Action::Upgrade { "Upgrade" } else { "Downgrade" };
+            let question = format!("{} {} migration(s) to {}? (y/n)", direction, count, self.target());
+            let area = centered(frame.area(), question.chars().count() as u16 + 4, 3);
+            frame.render_widget(Clear, area);
+            frame.render_widget(Paragraph::new(question).block(Block::default().borders(Borders::ALL).title(" Confirm ")), area);
+        }
+    }
+}
+
+/// `●` for applied and `○` for pending migrations, with merges, branch points and the current
+/// revision marked.
+fn graph_line(row: &Row) -> Line<'_> {
+    let (marker, color) = if row.applied { ("● ", Color::Green) } else { ("○ ", Color::Yellow) };
+    let mut spans = vec![
+        Span::styled(marker, Style::default().fg(color)),
+        Span::styled(format!("{} ", row.revision.chars().take(8).collect::<String>()), Style::default().fg(Color::Cyan)),
+        Span::raw(row.name.as_str()),
+    ];
+    if row.down_revisions.len() > 1 {
+        spans.push(Span::styled(format!(" (merges {})", row.down_revisions.join(", ")), Style::default().fg(Color::Magenta)));
+    }
+    if row.children > 1 {
+        spans.push(Span::styled(format!(" (branches {})", row.children), Style::default().fg(Color::Magenta)));
+    }
+    if row.current {
+        spans.push(Span::styled(" (current)", Style::default().add_modifier(Modifier::BOLD)));
+    }
+    Line::from(spans)
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+}
+
+/// The revision graph of `db_client`'s migration directory, newest first.
+fn load_rows(db_client: &mut DbClient) -> Result<Vec<Row>, MigrateError> {
+    let mut migrations = db_client._load_migrations()?;
+    db_client._sort_migrations(&mut migrations, true);
+    let current = db_client._get_current();
+    let mut rows = Vec::new();
+    for migration in migrations.iter().rev() {
+        rows.push(Row {
+            revision: migration.revision.clone(),
+            name: migration.name.clone(),
+            down_revisions: migration.down_revisions.clone(),
+            children: migrations.iter().filter(|m| m.down_revisions.contains(&migration.revision)).count(),
+            applied: db_client._is_applied(&migration.revision)?,
+            current: current.as_deref() == Some(migration.revision.as_str()),
+            up_sql: strip_headers(&migration.up_sql()?).to_string(),
+            down_sql: strip_headers(&migration.down_sql()?).to_string(),
+        });
+    }
+    Ok(rows)
+}
+
+/// Runs the TUI until the user quits.
+///
+/// The terminal is drawn on stderr while stdout, where `DbClient` reports progress, is captured
+/// and shown in the log pane.
+pub fn run(db_client: &mut DbClient) -> Result<(), MigrateError> {
+    let mut output = BufferRedirect::stdout()?;
+    let _screen = Screen::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+    event_loop(&mut terminal, db_client, &mut output)
+}
+
+/// Raw mode on the alternate screen, left again on drop so that an error or a panic in the event
+/// loop does not leave the terminal unusable.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        enable_raw_mode()?;
+        let screen = Screen;
+        execute!(io::stderr(), EnterAlternateScreen)?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+fn event_loop<B: Backend>(terminal: &mut Terminal<B>, db_client: &mut DbClient, output: &mut BufferRedirect) -> Result<(), MigrateError> {
+    let mut app = App::new(load_rows(db_client)?);
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame))?;
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let Some(action) = app.handle_key(key.code) else { continue };
+
+        for target in app.steps(action) {
+            let result = db_client.run_migrations(action == Action::Upgrade, false, Some(&target), None);
+            let mut printed = String::new();
+            output.read_to_string(&mut printed)?;
+            app.log.extend(printed.lines().map(str::to_string));
+            app.set_rows(load_rows(db_client)?);
+            if let Err(error) = result {
+                app.log.push(format!("Error: {}", error));
+                break;
+            }
+            terminal.draw(|frame| app.draw(frame))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    fn rows() -> Vec<Row> {
+        ["c", "b", "a"].iter().enumerate().map(|(index, revision)| Row {
+            revision: revision.to_string(),
+            name: format!("add_{}", revision),
+            down_revisions: Vec::new(),
+            children: 0,
+            applied: index > 0,
+            current: index == 1,
+            up_sql: format!("CREATE TABLE {} (id INT);", revision),
+            down_sql: format!("DROP TABLE {};", revision),
+        }).collect()
+    }
+
+    #[test]
+    fn test_steps_and_confirmation() {
+        let mut app = App::new(rows());
+        assert_eq!(app.selected, 1);
+        assert!(app.handle_key(KeyCode::Char('u')).is_none());
+        assert_eq!(app.log, vec!["Nothing to upgrade to b"]);
+
+        app.handle_key(KeyCode::Up);
+        assert_eq!(app.steps(Action::Upgrade), vec!["c"]);
+        app.handle_key(KeyCode::Char('u'));
+        assert_eq!(app.handle_key(KeyCode::Char('y')), Some(Action::Upgrade));
+
+        app.handle_key(KeyCode::Down);
+        app.handle_key(KeyCode::Down);
+        app.handle_key(KeyCode::Down);
+        assert_eq!(app.steps(Action::Downgrade), vec!["a", "base"]);
+        app.handle_key(KeyCode::Char('d'));
+        assert!(app.handle_key(KeyCode::Char('n')).is_none());
+        assert_eq!(app.log.last().unwrap(), "Cancelled");
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        let mut app = App::new(rows());
+        app.handle_key(KeyCode::Tab);
+        app.handle_key(KeyCode::Down);
+        app.handle_key(KeyCode::Char('d'));
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("○ c add_c"));
+        assert!(screen.contains("● b add_b (current)"));
+        assert!(screen.contains("DROP TABLE a;"));
+        assert!(screen.contains("Downgrade 1 migration(s) to a? (y/n)"));
+    }
+
+    #[test]
+    fn test_graph_line_non_ascii_revision() {
+        let row = Row { revision: "änderung_1234".to_string(), ..rows().remove(0) };
+        let line: String = graph_line(&row).spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(line, "○ änderung add_c");
+    }
+}
@@ -23,7 +23,7 @@ mod tests {
     use super::*;
     use std::env;
     use std::fs;
-    use pg_migrate::setup::get_db_client;
+    use pg_migrate::setup::{get_db_client, lock_db_client, TempDir};
 
     fn remove_test_migrations() {
         let migration_dir = env::var("MIGRATION_DIR").unwrap_or_else(|_| "migrations".to_string());
@@ -137,4 +137,37 @@ mod tests {
             "Head: 622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd"
         );
     }
+
+    #[test]
+    fn test_dump_schema() {
+        let mut db_client = lock_db_client();
+        let dir = TempDir::new();
+
+        db_client.set_schema_file(dir.join("upgrade_schema.sql").to_str().unwrap());
+        db_client.set_dump_schema(true);
+        db_client.run_migrations(true, true, None, None).expect("Failed to run migrations");
+        let upgrade_schema = fs::read_to_string(dir.join("upgrade_schema.sql")).unwrap();
+        assert!(upgrade_schema.starts_with("-- Schema generated by pg_migrate, do not edit\n-- Revision: 622511aa-d4ee-4ea7-a3c9-cd900bc2c2bd\n"));
+        assert!(upgrade_schema.contains("CREATE TABLE public.wallets (\n    id integer DEFAULT nextval('wallets_id_seq'::regclass) NOT NULL,"));
+        assert!(!upgrade_schema.contains("CREATE TABLE public.history"));
+
+        db_client.set_dump_schema(false);
+        db_client.run_migrations(false, false, None, Some(&2)).expect("Failed to run migrations");
+        let schema_file = dir.join("schema.sql");
+        let schema = db_client.dump_schema(Some(schema_file.to_str().unwrap())).expect("Failed to dump schema");
+        assert_eq!(fs::read_to_string(&schema_file).unwrap(), schema);
+        assert!(schema.starts_with("-- Schema generated by pg_migrate, do not edit\n-- Revision: None\n"));
+        assert!(!schema.contains("CREATE TABLE"));
+    }
+
+    #[test]
+    fn test_seeds_dry_run() {
+        let mut db_client = lock_db_client();
+        let seed_dir = TempDir::new();
+        fs::write(seed_dir.join("01_settings.sql"), "CREATE TABLE seeded_settings (id INT);\n").unwrap();
+        db_client.set_seed_dir(seed_dir.to_str().unwrap());
+
+        db_client.set_dry_run(true);
+        assert_eq!(db_client.run_seeds(None).expect("Failed to run seeds"), vec!["01_settings.sql"]);
+    }
 }